@@ -0,0 +1,233 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    calibration::Calibration,
+    error::BridgeError,
+    recording::{Recorder, RecordingFormat},
+    tracking::{
+        client::{TrackingClientType, TrackingOptions},
+        response::TrackingResponse,
+    },
+    transform::TransformationConfig,
+    vts::plugin::VTubeStudioPlugin,
+};
+
+#[derive(Debug, Clone)]
+struct BridgeSource {
+    client_type: TrackingClientType,
+    // Phone IP of the bridge is used when it's not set
+    ip: Option<String>,
+}
+
+// Tracking clients and the plugin, ready to be started
+#[derive(Debug, Clone)]
+pub struct Bridge {
+    sources: Vec<BridgeSource>,
+    phone_ip: String,
+    options: TrackingOptions,
+    config_path: String,
+    config_reload_delay: u64,
+    face_search_timeout: u64,
+    failover_timeout: u64,
+    vts_host: String,
+    vts_port: u16,
+    recording: Option<(String, RecordingFormat)>,
+    calibration_path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BridgeBuilder {
+    bridge: Bridge,
+}
+
+// Threads of a started bridge
+pub struct BridgeHandle {
+    active: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Bridge {
+    pub fn builder() -> BridgeBuilder {
+        BridgeBuilder {
+            bridge: Bridge {
+                sources: Vec::new(),
+                phone_ip: String::new(),
+                options: TrackingOptions::default(),
+                config_path: String::new(),
+                config_reload_delay: 0,
+                face_search_timeout: 3000,
+                failover_timeout: 1000,
+                vts_host: "localhost".to_string(),
+                vts_port: 8001,
+                recording: None,
+                calibration_path: None,
+            },
+        }
+    }
+
+    pub fn start(&self) -> Result<BridgeHandle, BridgeError> {
+        // Typo in the config is reported before anything is started
+        TransformationConfig::load(&self.config_path)?;
+        let calibration = match &self.calibration_path {
+            Some(path) => Calibration::load(path)?,
+            None => Calibration::default(),
+        };
+
+        let active = Arc::new(AtomicBool::new(true));
+        let mut threads = Vec::new();
+
+        let recorder = match &self.recording {
+            Some((directory, format)) => {
+                Some(Arc::new(Mutex::new(Recorder::create(directory, *format)?)))
+            }
+            None => None,
+        };
+
+        let mut receivers: Vec<(String, Receiver<TrackingResponse>)> = Vec::new();
+        for source in &self.sources {
+            let (sender, mut receiver) = mpsc::channel();
+
+            let function = source.client_type.client_fn();
+            let ip = source.ip.clone().unwrap_or(self.phone_ip.clone());
+            let options = self.options.clone();
+            let active_clone = Arc::clone(&active);
+            threads.push(thread::spawn(move || {
+                function(ip, options, sender, active_clone)
+            }));
+
+            // Prefix of variables, same clients are numbered: ifm, ifm2, ...
+            let short_name = source.client_type.short_name();
+            let mut name = short_name.to_string();
+            let mut index = 1;
+            while receivers.iter().any(|(used, _)| *used == name) {
+                index += 1;
+                name = format!("{short_name}{index}");
+            }
+
+            if let Some(recorder) = &recorder {
+                let (recorded_sender, recorded_receiver) = mpsc::channel();
+                let recorder = Arc::clone(recorder);
+                let source_name = name.clone();
+                let active_clone = Arc::clone(&active);
+                threads.push(thread::spawn(move || {
+                    Recorder::tee(
+                        recorder,
+                        source_name,
+                        receiver,
+                        recorded_sender,
+                        active_clone,
+                    )
+                }));
+                receiver = recorded_receiver;
+            }
+            receivers.push((name, receiver));
+        }
+
+        let plugin = VTubeStudioPlugin::new(
+            receivers,
+            self.config_path.clone(),
+            self.config_reload_delay,
+            self.face_search_timeout,
+            self.failover_timeout,
+        )
+        .with_vts_endpoint(self.vts_host.clone(), self.vts_port)
+        .with_calibration(calibration);
+        let active_clone = Arc::clone(&active);
+        threads.push(thread::spawn(move || plugin.run(active_clone)));
+
+        Ok(BridgeHandle { active, threads })
+    }
+}
+
+impl BridgeBuilder {
+    // The first source is primary, can be called several times
+    pub fn source(mut self, client_type: TrackingClientType, ip: Option<String>) -> Self {
+        self.bridge.sources.push(BridgeSource { client_type, ip });
+        self
+    }
+
+    // Empty lets VTubeStudio and MeowFace search for the phone
+    pub fn phone_ip(mut self, phone_ip: String) -> Self {
+        self.bridge.phone_ip = phone_ip;
+        self
+    }
+
+    pub fn options(mut self, options: TrackingOptions) -> Self {
+        self.bridge.options = options;
+        self
+    }
+
+    pub fn config(mut self, path: String) -> Self {
+        self.bridge.config_path = path;
+        self
+    }
+
+    // Milliseconds, 0 disables reloading
+    pub fn config_reload_delay(mut self, delay: u64) -> Self {
+        self.bridge.config_reload_delay = delay;
+        self
+    }
+
+    // Milliseconds
+    pub fn face_search_timeout(mut self, timeout: u64) -> Self {
+        self.bridge.face_search_timeout = timeout;
+        self
+    }
+
+    // Milliseconds, 0 disables switching to secondary sources
+    pub fn failover_timeout(mut self, timeout: u64) -> Self {
+        self.bridge.failover_timeout = timeout;
+        self
+    }
+
+    pub fn vts_endpoint(mut self, host: String, port: u16) -> Self {
+        self.bridge.vts_host = host;
+        self.bridge.vts_port = port;
+        self
+    }
+
+    pub fn record(mut self, directory: String, format: RecordingFormat) -> Self {
+        self.bridge.recording = Some((directory, format));
+        self
+    }
+
+    // Made with Calibration::record, without it X_cal variables equal X
+    pub fn calibration(mut self, path: String) -> Self {
+        self.bridge.calibration_path = Some(path);
+        self
+    }
+
+    pub fn build(self) -> Result<Bridge, BridgeError> {
+        if self.bridge.sources.is_empty() {
+            return Err(BridgeError::Config("No tracking sources".into()));
+        }
+        if self.bridge.config_path.is_empty() {
+            return Err(BridgeError::Config("No transformation config".into()));
+        }
+        Ok(self.bridge)
+    }
+}
+
+impl BridgeHandle {
+    // Threads notice it on their next timeout
+    pub fn stop(&self) {
+        self.active.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn join(self) {
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use evalexpr::{ContextWithMutableVariables, HashMapContext};
+
+use crate::{
+    error::BridgeError,
+    tracking::{
+        client::{TrackingClientType, TrackingOptions},
+        response::TrackingResponse,
+    },
+    transform::tracking_values,
+};
+
+// Waiting for the first frame with a face before calibration gives up
+const FACE_SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Resting value of a tracking variable
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug, Clone, Copy)]
+pub struct VariableCalibration {
+    pub neutral: f64,
+}
+
+// Neutral face of a performer: {"BrowDownLeft": {"neutral": 0.12}, ...}
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct Calibration {
+    pub variables: BTreeMap<String, VariableCalibration>,
+}
+
+impl Calibration {
+    pub fn load(path: &str) -> Result<Self, BridgeError> {
+        let calibration = fs::read_to_string(path).map_err(|error| {
+            BridgeError::Config(format!("Unable to read calibration {}: {}", path, error))
+        })?;
+        serde_json::from_str(&calibration).map_err(|error| {
+            BridgeError::Config(format!("Invalid calibration {}: {}", path, error))
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), BridgeError> {
+        fs::write(path, serde_json::to_string_pretty(self).unwrap())?;
+        Ok(())
+    }
+
+    // Averages of frames with a face, the rest are skipped
+    pub fn from_frames<'a>(
+        frames: impl IntoIterator<Item = &'a TrackingResponse>,
+    ) -> Result<Self, BridgeError> {
+        // Sum and count
+        let mut stats: BTreeMap<String, (f64, usize)> = BTreeMap::new();
+        for frame in frames.into_iter().filter(|frame| frame.face_found) {
+            for (name, value) in tracking_values(frame) {
+                let (sum, count) = stats.entry(name.to_string()).or_insert((0.0, 0));
+                *sum += value;
+                *count += 1;
+            }
+        }
+        if stats.is_empty() {
+            return Err(BridgeError::Config(
+                "No face was tracked while calibrating".into(),
+            ));
+        }
+
+        let variables = stats
+            .into_iter()
+            .map(|(name, (sum, count))| {
+                let neutral = sum / count as f64;
+                (name, VariableCalibration { neutral })
+            })
+            .collect();
+        Ok(Self { variables })
+    }
+
+    // Runs the tracking client until it has sent frames with a face for the duration
+    pub fn record(
+        client_type: TrackingClientType,
+        ip: String,
+        options: TrackingOptions,
+        duration: Duration,
+    ) -> Result<Self, BridgeError> {
+        let (sender, receiver) = mpsc::channel();
+        let active = Arc::new(AtomicBool::new(true));
+        let function = client_type.client_fn();
+        let active_clone = Arc::clone(&active);
+        let client = thread::spawn(move || function(ip, options, sender, active_clone));
+
+        let started = Instant::now();
+        let mut first_face: Option<Instant> = None;
+        let mut frames = Vec::new();
+        loop {
+            let deadline = match first_face {
+                Some(first_face) => first_face + duration,
+                None => started + FACE_SEARCH_TIMEOUT,
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match receiver.recv_timeout(deadline - now) {
+                Ok(frame) => {
+                    if frame.face_found && first_face.is_none() {
+                        first_face = Some(Instant::now());
+                    }
+                    frames.push(frame);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        active.store(false, Ordering::Relaxed);
+        let _ = client.join();
+        Self::from_frames(&frames)
+    }
+}
+
+// Sets X_cal as X minus its neutral value for every value of the current source.
+// Variables missing in the calibration have no offset, so configs work without one.
+// A name already holding a value of another type is left as is and its error is returned.
+pub fn insert_calibrated_info(
+    context: &mut HashMapContext,
+    calibration: &Calibration,
+    raw_data: &TrackingResponse,
+) -> Result<(), BridgeError> {
+    let mut result = Ok(());
+    for (name, value) in tracking_values(raw_data) {
+        let neutral = calibration
+            .variables
+            .get(name)
+            .map_or(0.0, |variable| variable.neutral);
+        let name = format!("{name}_cal");
+        if let Err(error) = context.set_value(name.clone(), (value - neutral).into()) {
+            result = Err(BridgeError::expression(&name, error));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use evalexpr::Context;
+
+    use super::*;
+    use crate::tracking::response::{Cords, Shape};
+
+    fn frame(face_found: bool, jaw_open: f64, head_rot_x: f64) -> TrackingResponse {
+        TrackingResponse {
+            timestamp: 0,
+            hotkey: 0,
+            face_found,
+            rotation: Cords {
+                x: head_rot_x,
+                y: 0.0,
+                z: 0.0,
+            },
+            position: Cords::default(),
+            eye_left: Cords::default(),
+            blend_shapes: vec![Shape {
+                k: "JawOpen".into(),
+                v: jaw_open,
+            }],
+        }
+    }
+
+    #[test]
+    fn averages_frames_with_face() {
+        let frames = [
+            frame(true, 0.1, -4.0),
+            frame(false, 1.0, 20.0),
+            frame(true, 0.3, -2.0),
+        ];
+        let calibration = Calibration::from_frames(&frames).unwrap();
+
+        assert!((calibration.variables["JawOpen"].neutral - 0.2).abs() < 1e-12);
+        assert_eq!(calibration.variables["HeadRotX"].neutral, -3.0);
+        assert_eq!(calibration.variables["HeadPosZ"].neutral, 0.0);
+        // FaceFound is not calibrated
+        assert_eq!(calibration.variables.len(), 7);
+
+        assert!(Calibration::from_frames(&[frame(false, 0.1, 0.0)]).is_err());
+        assert!(Calibration::from_frames(&[]).is_err());
+    }
+
+    #[test]
+    fn reads_calibration_with_ranges() {
+        let calibration: Calibration = serde_json::from_str(
+            r#"{"JawOpen": {"neutral": 0.25, "min": 0.2, "max": 0.3}, "HeadRotX": {"neutral": -4}}"#,
+        )
+        .unwrap();
+        assert_eq!(calibration.variables["JawOpen"].neutral, 0.25);
+        assert_eq!(calibration.variables["HeadRotX"].neutral, -4.0);
+    }
+
+    #[test]
+    fn sets_calibrated_values() {
+        let calibration = Calibration {
+            variables: BTreeMap::from([
+                ("JawOpen".to_string(), VariableCalibration { neutral: 0.25 }),
+                (
+                    "HeadRotX".to_string(),
+                    VariableCalibration { neutral: -4.0 },
+                ),
+            ]),
+        };
+        let mut context = HashMapContext::new();
+        insert_calibrated_info(&mut context, &calibration, &frame(true, 0.75, 6.0)).unwrap();
+
+        assert_eq!(context.get_value("JawOpen_cal"), Some(&0.5.into()));
+        assert_eq!(context.get_value("HeadRotX_cal"), Some(&10.0.into()));
+        // Missing in the calibration
+        assert_eq!(context.get_value("HeadRotY_cal"), Some(&0.0.into()));
+        assert_eq!(context.get_value("FaceFound_cal"), None);
+
+        // Name holding a value of another type
+        let mut context = HashMapContext::new();
+        context
+            .set_value("HeadPosX_cal".into(), "text".into())
+            .unwrap();
+        assert!(
+            insert_calibrated_info(&mut context, &calibration, &frame(true, 0.5, 0.0)).is_err()
+        );
+        assert_eq!(context.get_value("JawOpen_cal"), Some(&0.25.into()));
+    }
+}
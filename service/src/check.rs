@@ -0,0 +1,496 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{Display, Formatter},
+    sync::LazyLock,
+};
+
+use regex::Regex;
+
+use crate::{
+    curve::CurveConfig,
+    error::BridgeError,
+    filter::FilterConfig,
+    functions::is_function,
+    tracking::{
+        arkit::BLEND_SHAPES,
+        client::TrackingClientType,
+        openseeface::{FEATURES, LANDMARKS},
+    },
+    transform::{
+        expand_history, extract_wave_pingpong_numbers, read_config_file, read_identifiers,
+        variable_order,
+    },
+};
+
+// Set by every tracking client besides blendshapes
+const TRACKING_VARIABLES: [&str; 13] = [
+    "FaceFound",
+    "HeadPosX",
+    "HeadPosY",
+    "HeadPosZ",
+    "HeadRotX",
+    "HeadRotY",
+    "HeadRotZ",
+    "LeftEyeX",
+    "LeftEyeY",
+    "LeftEyeZ",
+    "RightEyeX",
+    "RightEyeY",
+    "RightEyeZ",
+];
+
+// Set for every frame, self_prev only while evaluating a transformation
+const HISTORY_VARIABLES: [&str; 2] = ["dt", "self_prev"];
+
+static CYCLIC_VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(Wave|PingPong)\d+$").unwrap());
+static LANDMARK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^Landmark(\d+)[XY]$").unwrap());
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Severity {
+    Error,
+    // Probably fine, e.g. a blendshape sent only by some VMC apps
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    // Position of the transformation in the config starting with 1, none for let variables
+    pub index: Option<usize>,
+    // Parameter or let variable
+    pub name: String,
+    pub message: String,
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.index {
+            Some(index) => write!(
+                f,
+                "{}: #{} {}: {}",
+                severity, index, self.name, self.message
+            ),
+            None => write!(f, "{}: let {}: {}", severity, self.name, self.message),
+        }
+    }
+}
+
+// Finds every problem of the config instead of stopping at the first one like loading does.
+// Unreadable files and invalid JSON are returned as an error.
+pub fn check_config(path: &str) -> Result<Vec<ConfigIssue>, BridgeError> {
+    let config_file = read_config_file(path)?;
+    let lets: Vec<&str> = config_file.variables.keys().map(String::as_str).collect();
+
+    let mut issues = Vec::new();
+    let mut used_lets: HashSet<String> = HashSet::new();
+
+    let mut dependencies = BTreeMap::new();
+    for (name, func) in &config_file.variables {
+        let mut report = |severity, message: String| {
+            issues.push(ConfigIssue {
+                severity,
+                index: None,
+                name: name.clone(),
+                message,
+            })
+        };
+
+        if !is_identifier(name) {
+            report(Severity::Error, "Name can't be used as a variable".into());
+        } else if is_tracking_variable(name)
+            || CYCLIC_VARIABLE.is_match(name)
+            || HISTORY_VARIABLES.contains(&name.as_str())
+        {
+            report(
+                Severity::Error,
+                "Name hides the variable of the bridge with the same name".into(),
+            );
+        }
+
+        if let Some(identifiers) = check_func(func, &lets, &mut report) {
+            if identifiers
+                .iter()
+                .any(|identifier| identifier == "self_prev")
+            {
+                report(
+                    Severity::Error,
+                    "self_prev is only set in transformations".into(),
+                );
+            }
+            used_lets.extend(identifiers.iter().map(|identifier| unprefixed(identifier)));
+            dependencies.insert(name.clone(), identifiers);
+        }
+    }
+    if let Err(cycle) = variable_order(&dependencies) {
+        issues.push(ConfigIssue {
+            severity: Severity::Error,
+            index: None,
+            name: cycle[0].clone(),
+            message: format!("Cycle in let variables: {}", cycle.join(" -> ")),
+        });
+    }
+
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    for (index, func) in config_file.transformations.iter().enumerate() {
+        let index = index + 1;
+        let mut report = |severity, message: String| {
+            issues.push(ConfigIssue {
+                severity,
+                index: Some(index),
+                name: func.name.clone(),
+                message,
+            })
+        };
+
+        if func.name.is_empty() {
+            report(Severity::Error, "Empty name".into());
+        } else if let Some(first) = names.get(func.name.as_str()) {
+            report(
+                Severity::Error,
+                format!("Duplicate name, already used by #{}", first),
+            );
+        } else {
+            names.insert(&func.name, index);
+        }
+
+        if func.min > func.max {
+            report(
+                Severity::Error,
+                format!("min {} is greater than max {}", func.min, func.max),
+            );
+        } else if func.default_value < func.min || func.default_value > func.max {
+            report(
+                Severity::Error,
+                format!(
+                    "defaultValue {} is outside of {}...{}",
+                    func.default_value, func.min, func.max
+                ),
+            );
+        }
+
+        if let Some(Err(message)) = func.curve.as_ref().map(CurveConfig::validate) {
+            report(Severity::Error, format!("Invalid curve: {}", message));
+        }
+        if let Some(Err(message)) = func.filter.as_ref().map(FilterConfig::validate) {
+            report(Severity::Error, format!("Invalid filter: {}", message));
+        }
+
+        if let Some(identifiers) = check_func(&func.func, &lets, &mut report) {
+            used_lets.extend(identifiers.iter().map(|identifier| unprefixed(identifier)));
+        }
+    }
+
+    for name in lets {
+        if !used_lets.contains(name) {
+            issues.push(ConfigIssue {
+                severity: Severity::Warning,
+                index: None,
+                name: name.to_string(),
+                message: "Variable is not used".into(),
+            });
+        }
+    }
+
+    Ok(issues)
+}
+
+// prev.X -> X
+fn unprefixed(identifier: &str) -> String {
+    identifier
+        .strip_prefix("prev.")
+        .unwrap_or(identifier)
+        .to_string()
+}
+
+// Returns identifiers read by the function with prev(X) as prev.X, none when it can't be parsed
+fn check_func(
+    func: &str,
+    lets: &[&str],
+    report: &mut impl FnMut(Severity, String),
+) -> Option<Vec<String>> {
+    let node = match evalexpr::build_operator_tree(func) {
+        Ok(node) => node,
+        Err(error) => {
+            report(Severity::Error, format!("Invalid func: {}", error));
+            return None;
+        }
+    };
+
+    let expanded = evalexpr::build_operator_tree(&expand_history(func)).ok()?;
+    if expanded
+        .iter_function_identifiers()
+        .any(|function| function == "prev" || function == "delta")
+    {
+        report(
+            Severity::Error,
+            "prev and delta take the name of a variable, e.g. prev(JawOpen)".into(),
+        );
+    }
+
+    let mut unknown: Vec<&str> = expanded
+        .iter_function_identifiers()
+        .filter(|function| !is_function(function) && *function != "prev" && *function != "delta")
+        .collect();
+    unknown.sort();
+    unknown.dedup();
+    for function in unknown {
+        report(
+            Severity::Error,
+            format!("Unknown function \"{}\"", function),
+        );
+    }
+
+    if extract_wave_pingpong_numbers(func).contains(&0) {
+        report(
+            Severity::Error,
+            "Wave0 and PingPong0 have no cycle duration".into(),
+        );
+    }
+
+    let mut checked: Vec<&str> = Vec::new();
+    for identifier in node.iter_write_variable_identifiers() {
+        if !checked.contains(&identifier) {
+            checked.push(identifier);
+            report(
+                Severity::Error,
+                format!("Assignment to \"{}\" is not supported", identifier),
+            );
+        }
+    }
+    for identifier in node.iter_read_variable_identifiers() {
+        if checked.contains(&identifier) || lets.contains(&identifier) {
+            continue;
+        }
+        checked.push(identifier);
+        if let Some((severity, message)) = check_variable(identifier) {
+            report(severity, message);
+        }
+    }
+
+    Some(read_identifiers(&expanded))
+}
+
+fn is_identifier(name: &str) -> bool {
+    evalexpr::build_operator_tree(name).is_ok_and(|node| node.iter_identifiers().eq([name]))
+}
+
+fn is_source_name(prefix: &str) -> bool {
+    // Same clients are numbered: ifm, ifm2, ...
+    TrackingClientType::ALL.iter().any(|client_type| {
+        prefix
+            .strip_prefix(client_type.short_name())
+            .is_some_and(|number| number.is_empty() || number.parse::<u32>().is_ok_and(|n| n > 1))
+    })
+}
+
+fn is_tracking_variable(name: &str) -> bool {
+    BLEND_SHAPES.contains(&name)
+        || TRACKING_VARIABLES.contains(&name)
+        || FEATURES.contains(&name)
+        || LANDMARK
+            .captures(name)
+            .and_then(|captures| captures[1].parse::<usize>().ok())
+            .is_some_and(|landmark| landmark < LANDMARKS)
+}
+
+fn check_variable(identifier: &str) -> Option<(Severity, String)> {
+    if CYCLIC_VARIABLE.is_match(identifier) || HISTORY_VARIABLES.contains(&identifier) {
+        return None;
+    }
+
+    let (prefix, name) = match identifier.split_once('.') {
+        Some((prefix, name)) => {
+            if !is_source_name(prefix) {
+                let prefixes: Vec<&str> = TrackingClientType::ALL
+                    .iter()
+                    .map(TrackingClientType::short_name)
+                    .collect();
+                return Some((
+                    Severity::Error,
+                    format!(
+                        "Unknown tracking source \"{}\" in \"{}\", sources are {}",
+                        prefix,
+                        identifier,
+                        prefixes.join(", ")
+                    ),
+                ));
+            }
+            (format!("{prefix}."), name)
+        }
+        None => (String::new(), identifier),
+    };
+    // Calibrated X_cal is set only without a prefix
+    let (name, suffix) = match name.strip_suffix("_cal") {
+        Some(base) if prefix.is_empty() && base != "FaceFound" => (base, "_cal"),
+        _ => (name, ""),
+    };
+    if is_tracking_variable(name) {
+        return None;
+    }
+
+    // Close to a known name is most likely a typo, anything else may come from a VMC app
+    match suggest(name) {
+        Some(suggestion) => Some((
+            Severity::Error,
+            format!(
+                "Unknown variable \"{}\", did you mean \"{}{}{}\"?",
+                identifier, prefix, suggestion, suffix
+            ),
+        )),
+        None => Some((
+            Severity::Warning,
+            format!(
+                "Unknown variable \"{}\", it's not sent by any tracking app except some VMC apps",
+                identifier
+            ),
+        )),
+    }
+}
+
+fn suggest(name: &str) -> Option<&'static str> {
+    BLEND_SHAPES
+        .iter()
+        .chain(TRACKING_VARIABLES.iter())
+        .chain(FEATURES.iter())
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance ignoring case, so "jawOpen" is suggested to be "JawOpen"
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + (a_char != b_char) as usize;
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn check(name: &str, config: &str) -> Vec<String> {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, config).unwrap();
+        check_config(path.to_str().unwrap())
+            .unwrap()
+            .iter()
+            .map(ConfigIssue::to_string)
+            .collect()
+    }
+
+    fn parameter(name: &str, func: &str) -> String {
+        format!(r#"{{"name": "{name}", "func": "{func}", "min": 0, "max": 1, "defaultValue": 0}}"#)
+    }
+
+    #[test]
+    fn accepts_valid_config() {
+        let config = format!(
+            r#"{{"let": {{"open": "max(JawOpen_cal, ifm2.JawOpen)"}}, "transformations": [{}, {}]}}"#,
+            parameter(
+                "Open",
+                "remap(open, 0, 1, 0, 1) * Wave1000 + delta(HeadRotX) / dt"
+            ),
+            parameter(
+                "Sway",
+                "if(FaceFound == 1, math::sin(self_prev), 0) + Landmark67X"
+            ),
+        );
+        assert_eq!(
+            check("sandoitchi_check_valid.json", &config),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn reports_cycles_of_let_variables() {
+        let config = format!(
+            r#"{{"let": {{"a": "b + 1", "b": "prev(b) + a", "c": "c"}}, "transformations": [{}]}}"#,
+            parameter("Open", "a + c"),
+        );
+        assert_eq!(
+            check("sandoitchi_check_cycle.json", &config),
+            ["error: let a: Cycle in let variables: a -> b -> a"]
+        );
+    }
+
+    #[test]
+    fn suggests_names_of_typos() {
+        let config = format!(
+            "[{}]",
+            parameter(
+                "Open",
+                "jawOpen + ifm.HeadRotx + EyeBlinkLeft_cal + BoneTwist"
+            )
+        );
+        assert_eq!(
+            check("sandoitchi_check_typo.json", &config),
+            [
+                "error: #1 Open: Unknown variable \"jawOpen\", did you mean \"JawOpen\"?",
+                "error: #1 Open: Unknown variable \"ifm.HeadRotx\", did you mean \"ifm.HeadRotX\"?",
+                "warning: #1 Open: Unknown variable \"BoneTwist\", it's not sent by any tracking app except some VMC apps",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_duplicate_names_and_ranges() {
+        let config = r#"[
+            {"name": "Open", "func": "JawOpen", "min": 0, "max": 1, "defaultValue": 0},
+            {"name": "Open", "func": "JawOpen", "min": 1, "max": 0, "defaultValue": 0},
+            {"name": "Wide", "func": "JawOpen", "min": 0, "max": 1, "defaultValue": 2}
+        ]"#;
+        assert_eq!(
+            check("sandoitchi_check_names.json", config),
+            [
+                "error: #2 Open: Duplicate name, already used by #1",
+                "error: #2 Open: min 1 is greater than max 0",
+                "error: #3 Wide: defaultValue 2 is outside of 0...1",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_functions_and_sources() {
+        let config = format!(
+            "[{}, {}]",
+            parameter(
+                "Open",
+                "smooth(JawOpen) + math::sin(JawOpen) + sign(x.JawOpen)"
+            ),
+            parameter("Wide", "vmc0.MouthLeft + vtss.MouthRight"),
+        );
+        let sources = "sources are vts, ifm, vmc, osf, fm3d, meow, llf, replay";
+        let issues = check("sandoitchi_check_functions.json", &config);
+        assert_eq!(issues[0], "error: #1 Open: Unknown function \"smooth\"");
+        assert_eq!(
+            issues[1],
+            format!("error: #1 Open: Unknown tracking source \"x\" in \"x.JawOpen\", {sources}")
+        );
+        assert_eq!(
+            issues[2],
+            format!(
+                "error: #2 Wide: Unknown tracking source \"vmc0\" in \"vmc0.MouthLeft\", {sources}"
+            )
+        );
+        assert!(issues[3].starts_with("error: #2 Wide: Unknown tracking source \"vtss\""));
+        assert_eq!(issues.len(), 4);
+    }
+}
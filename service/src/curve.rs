@@ -0,0 +1,192 @@
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+    PartialEq,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Smooth through every point, may overshoot between points
+    CatmullRom,
+}
+
+// Points are [input, output] with increasing inputs. Outside of them the first or the last output is used.
+#[derive(serde::Serialize, serde::Deserialize, schemars::JsonSchema, PartialEq, Debug, Clone)]
+#[serde(
+    untagged,
+    expecting = "curve has to be a list of [input, output] points or a map with points and interpolation"
+)]
+#[schemars(rename = "Curve")]
+pub enum CurveConfig {
+    // Linear interpolation
+    Points(Vec<[f64; 2]>),
+    Interpolated {
+        points: Vec<[f64; 2]>,
+        #[serde(default)]
+        interpolation: Interpolation,
+    },
+}
+
+impl CurveConfig {
+    pub fn points(&self) -> &[[f64; 2]] {
+        match self {
+            CurveConfig::Points(points) => points,
+            CurveConfig::Interpolated { points, .. } => points,
+        }
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        match self {
+            CurveConfig::Points(_) => Interpolation::Linear,
+            CurveConfig::Interpolated { interpolation, .. } => *interpolation,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let points = self.points();
+        if points.len() < 2 {
+            return Err("at least 2 points are needed".into());
+        }
+        if let Some([input, output]) = points
+            .iter()
+            .find(|[input, output]| !input.is_finite() || !output.is_finite())
+        {
+            return Err(format!("[{}, {}] is not a point", input, output));
+        }
+        if let Some(pair) = points.windows(2).find(|pair| pair[0][0] >= pair[1][0]) {
+            return Err(format!(
+                "inputs have to increase, {} comes after {}",
+                pair[1][0], pair[0][0]
+            ));
+        }
+        Ok(())
+    }
+
+    // Expects a validated curve
+    pub fn apply(&self, input: f64) -> f64 {
+        let points = self.points();
+        let [first, last] = [points[0], points[points.len() - 1]];
+        if input.is_nan() {
+            return input;
+        }
+        if input <= first[0] {
+            return first[1];
+        }
+        if input >= last[0] {
+            return last[1];
+        }
+
+        // Segment with the input, between points[index] and points[index + 1]
+        let index = points.partition_point(|point| point[0] <= input) - 1;
+        let [x1, y1] = points[index];
+        let [x2, y2] = points[index + 1];
+        let width = x2 - x1;
+        let t = (input - x1) / width;
+
+        match self.interpolation() {
+            Interpolation::Linear => y1 + (y2 - y1) * t,
+            // Cubic Hermite with Catmull-Rom tangents, so points don't have to be evenly spaced
+            Interpolation::CatmullRom => {
+                let tangent = |index: usize| {
+                    let [x0, y0] = points[index.saturating_sub(1)];
+                    let [x2, y2] = points[(index + 1).min(points.len() - 1)];
+                    (y2 - y0) / (x2 - x0)
+                };
+                let (t2, t3) = (t * t, t * t * t);
+                (2.0 * t3 - 3.0 * t2 + 1.0) * y1
+                    + (t3 - 2.0 * t2 + t) * width * tangent(index)
+                    + (-2.0 * t3 + 3.0 * t2) * y2
+                    + (t3 - t2) * width * tangent(index + 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [[f64; 2]; 3] = [[0.0, 0.0], [0.3, 0.1], [1.0, 1.0]];
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn linear_interpolates_between_points() {
+        let curve = CurveConfig::Points(POINTS.to_vec());
+        assert!(close(curve.apply(0.15), 0.05));
+        assert!(close(curve.apply(0.5), 0.1 + 0.9 * 0.2 / 0.7));
+        for [input, output] in POINTS {
+            assert!(close(curve.apply(input), output));
+        }
+    }
+
+    #[test]
+    fn inputs_outside_use_first_and_last_output() {
+        let curve = CurveConfig::Interpolated {
+            points: POINTS.to_vec(),
+            interpolation: Interpolation::CatmullRom,
+        };
+        assert_eq!(curve.apply(-5.0), 0.0);
+        assert_eq!(curve.apply(f64::NEG_INFINITY), 0.0);
+        assert_eq!(curve.apply(1.5), 1.0);
+        assert_eq!(curve.apply(f64::INFINITY), 1.0);
+        assert!(curve.apply(f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn catmull_rom_uses_one_sided_tangents_at_ends() {
+        let curve = CurveConfig::Interpolated {
+            points: POINTS.to_vec(),
+            interpolation: Interpolation::CatmullRom,
+        };
+        assert!(close(curve.apply(0.15), 0.025));
+        assert!(close(curve.apply(0.5), 0.327988338));
+        for [input, output] in POINTS {
+            assert!(close(curve.apply(input), output));
+        }
+
+        // Straight line stays straight with end tangents along the line
+        let line = CurveConfig::Interpolated {
+            points: vec![[0.0, 0.0], [0.5, 1.0], [2.0, 4.0]],
+            interpolation: Interpolation::CatmullRom,
+        };
+        for input in [0.1, 0.25, 0.7, 1.9] {
+            assert!(close(line.apply(input), input * 2.0));
+        }
+    }
+
+    #[test]
+    fn validates_points() {
+        assert!(CurveConfig::Points(POINTS.to_vec()).validate().is_ok());
+        assert!(CurveConfig::Points(vec![[0.0, 0.0]]).validate().is_err());
+        assert!(CurveConfig::Points(vec![[0.0, 0.0], [f64::NAN, 1.0]])
+            .validate()
+            .is_err());
+        assert_eq!(
+            CurveConfig::Points(vec![[0.0, 0.0], [0.5, 1.0], [0.5, 0.0]]).validate(),
+            Err("inputs have to increase, 0.5 comes after 0.5".into())
+        );
+        assert!(CurveConfig::Points(vec![[1.0, 0.0], [0.0, 1.0]])
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn reads_both_forms() {
+        let points: CurveConfig = serde_json::from_str("[[0, 0], [1, 2]]").unwrap();
+        assert_eq!(points.interpolation(), Interpolation::Linear);
+        let interpolated: CurveConfig =
+            serde_json::from_str(r#"{"points": [[0, 0], [1, 2]], "interpolation": "catmullRom"}"#)
+                .unwrap();
+        assert_eq!(interpolated.interpolation(), Interpolation::CatmullRom);
+        assert_eq!(interpolated.points(), points.points());
+    }
+}
@@ -0,0 +1,52 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum BridgeError {
+    // Missing or unreadable transformation config, invalid bridge settings
+    Config(String),
+    // Parameter with a function failing to build or evaluate
+    Expression { parameter: String, message: String },
+    // Malformed frame from a tracking app
+    Protocol(String),
+    // Unexpected message from VTubeStudio
+    VtsApi(String),
+    Io(std::io::Error),
+}
+
+impl BridgeError {
+    pub fn expression(parameter: &str, error: impl Display) -> Self {
+        BridgeError::Expression {
+            parameter: parameter.to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl Display for BridgeError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            BridgeError::Config(message) => write!(f, "Config error: {}", message),
+            BridgeError::Expression { parameter, message } => {
+                write!(f, "Expression error in {}: {}", parameter, message)
+            }
+            BridgeError::Protocol(message) => write!(f, "Protocol error: {}", message),
+            BridgeError::VtsApi(message) => write!(f, "VTubeStudio API error: {}", message),
+            BridgeError::Io(error) => write!(f, "IO error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for BridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BridgeError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for BridgeError {
+    fn from(error: std::io::Error) -> Self {
+        BridgeError::Io(error)
+    }
+}
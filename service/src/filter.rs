@@ -0,0 +1,274 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+#[derive(serde::Serialize, serde::Deserialize, schemars::JsonSchema, PartialEq, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[schemars(rename = "Filter")]
+pub enum FilterConfig {
+    // Exponential moving average, alpha 1 is no smoothing
+    Ema {
+        alpha: f64,
+    },
+    // 1€ filter: smooth while still, quick while moving. Cutoffs are in Hz.
+    #[serde(rename_all = "camelCase")]
+    OneEuro {
+        min_cutoff: f64,
+        beta: f64,
+        #[serde(default = "default_d_cutoff")]
+        d_cutoff: f64,
+    },
+    // Estimates a constant value, a bigger measurement noise smooths more
+    #[serde(rename_all = "camelCase")]
+    Kalman {
+        process_noise: f64,
+        measurement_noise: f64,
+    },
+}
+
+fn default_d_cutoff() -> f64 {
+    1.0
+}
+
+impl FilterConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            FilterConfig::Ema { alpha } if !(alpha > 0.0 && alpha <= 1.0) => {
+                Err(format!("alpha {} is outside of 0...1", alpha))
+            }
+            FilterConfig::OneEuro {
+                min_cutoff,
+                beta,
+                d_cutoff,
+            } if !(min_cutoff > 0.0 && beta >= 0.0 && d_cutoff > 0.0) => Err(format!(
+                "minCutoff {} and dCutoff {} have to be positive, beta {} can't be negative",
+                min_cutoff, d_cutoff, beta
+            )),
+            FilterConfig::Kalman {
+                process_noise,
+                measurement_noise,
+            } if !(process_noise > 0.0 && measurement_noise > 0.0) => Err(format!(
+                "processNoise {} and measurementNoise {} have to be positive",
+                process_noise, measurement_noise
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum FilterState {
+    Ema {
+        value: f64,
+    },
+    OneEuro {
+        value: f64,
+        derivative: f64,
+        time: f64,
+    },
+    Kalman {
+        value: f64,
+        covariance: f64,
+    },
+}
+
+// Smoothing factor of a low-pass filter with the cutoff in Hz for the time step in seconds
+fn smoothing_factor(cutoff: f64, step: f64) -> f64 {
+    let tau = 1.0 / (2.0 * PI * cutoff);
+    1.0 / (1.0 + tau / step)
+}
+
+impl FilterState {
+    fn new(config: &FilterConfig, value: f64, time: f64) -> Self {
+        match config {
+            FilterConfig::Ema { .. } => FilterState::Ema { value },
+            FilterConfig::OneEuro { .. } => FilterState::OneEuro {
+                value,
+                derivative: 0.0,
+                time,
+            },
+            FilterConfig::Kalman {
+                measurement_noise, ..
+            } => FilterState::Kalman {
+                value,
+                covariance: *measurement_noise,
+            },
+        }
+    }
+
+    // None when the state belongs to another type of filter
+    fn apply(&mut self, config: &FilterConfig, input: f64, time: f64) -> Option<f64> {
+        match (self, config) {
+            (FilterState::Ema { value }, FilterConfig::Ema { alpha }) => {
+                *value += alpha * (input - *value);
+                Some(*value)
+            }
+            (
+                FilterState::OneEuro {
+                    value,
+                    derivative,
+                    time: last_time,
+                },
+                FilterConfig::OneEuro {
+                    min_cutoff,
+                    beta,
+                    d_cutoff,
+                },
+            ) => {
+                let step = time - *last_time;
+                // Several values in the same millisecond
+                if step <= 0.0 {
+                    return Some(*value);
+                }
+                *last_time = time;
+
+                let raw_derivative = (input - *value) / step;
+                *derivative += smoothing_factor(*d_cutoff, step) * (raw_derivative - *derivative);
+                let cutoff = min_cutoff + beta * derivative.abs();
+                *value += smoothing_factor(cutoff, step) * (input - *value);
+                Some(*value)
+            }
+            // Frames are treated as equal steps, the time is not used
+            (
+                FilterState::Kalman { value, covariance },
+                FilterConfig::Kalman {
+                    process_noise,
+                    measurement_noise,
+                },
+            ) => {
+                *covariance += process_noise;
+                let gain = *covariance / (*covariance + measurement_noise);
+                *value += gain * (input - *value);
+                *covariance *= 1.0 - gain;
+                Some(*value)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Filter states of parameters, kept between frames
+#[derive(Debug, Default)]
+pub struct Filters {
+    states: HashMap<String, FilterState>,
+}
+
+impl Filters {
+    // Time is in milliseconds, the first value of a parameter is returned as is
+    pub fn apply(&mut self, name: &str, config: &FilterConfig, value: f64, time_ms: u128) -> f64 {
+        // NaN would stay in the state forever
+        if !value.is_finite() {
+            return value;
+        }
+
+        let time = time_ms as f64 / 1000.0;
+        if let Some(state) = self.states.get_mut(name) {
+            if let Some(filtered) = state.apply(config, value, time) {
+                return filtered;
+            }
+        }
+        self.states
+            .insert(name.to_string(), FilterState::new(config, value, time));
+        value
+    }
+
+    // Config reload starts filtering over
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMA: FilterConfig = FilterConfig::Ema { alpha: 0.5 };
+    const ONE_EURO: FilterConfig = FilterConfig::OneEuro {
+        min_cutoff: 1.0,
+        beta: 0.0,
+        d_cutoff: 1.0,
+    };
+    const KALMAN: FilterConfig = FilterConfig::Kalman {
+        process_noise: 0.01,
+        measurement_noise: 0.1,
+    };
+
+    #[test]
+    fn first_value_is_returned_as_is() {
+        for config in [EMA, ONE_EURO, KALMAN] {
+            let mut filters = Filters::default();
+            assert_eq!(filters.apply("A", &config, 0.7, 1000), 0.7);
+        }
+    }
+
+    #[test]
+    fn ema_moves_by_alpha() {
+        let mut filters = Filters::default();
+        filters.apply("A", &EMA, 0.0, 0);
+        assert_eq!(filters.apply("A", &EMA, 1.0, 16), 0.5);
+        assert_eq!(filters.apply("A", &EMA, 1.0, 32), 0.75);
+    }
+
+    #[test]
+    fn one_euro_keeps_value_without_time_step() {
+        let mut filters = Filters::default();
+        filters.apply("A", &ONE_EURO, 0.0, 1000);
+        let value = filters.apply("A", &ONE_EURO, 1.0, 1100);
+        assert!(value > 0.0 && value < 1.0);
+
+        // Same millisecond and a clock going back
+        assert_eq!(filters.apply("A", &ONE_EURO, 5.0, 1100), value);
+        assert_eq!(filters.apply("A", &ONE_EURO, 5.0, 900), value);
+        assert!(filters.apply("A", &ONE_EURO, 1.0, 1200) > value);
+    }
+
+    #[test]
+    fn nan_does_not_reach_the_state() {
+        for config in [EMA, ONE_EURO, KALMAN] {
+            let mut filters = Filters::default();
+            filters.apply("A", &config, 1.0, 0);
+            assert!(filters.apply("A", &config, f64::NAN, 16).is_nan());
+            assert_eq!(filters.apply("A", &config, 1.0, 32), 1.0);
+        }
+    }
+
+    #[test]
+    fn other_type_of_filter_starts_over() {
+        let mut filters = Filters::default();
+        filters.apply("A", &EMA, 0.0, 0);
+        filters.apply("A", &EMA, 1.0, 16);
+        // Config reloaded with another filter for the parameter
+        assert_eq!(filters.apply("A", &KALMAN, 1.0, 32), 1.0);
+        assert!(filters.apply("A", &KALMAN, 0.0, 48) < 1.0);
+        assert_eq!(filters.apply("A", &ONE_EURO, 0.3, 64), 0.3);
+
+        filters.clear();
+        assert_eq!(filters.apply("A", &ONE_EURO, 0.9, 80), 0.9);
+    }
+
+    #[test]
+    fn parameters_have_own_states() {
+        let mut filters = Filters::default();
+        filters.apply("A", &EMA, 0.0, 0);
+        assert_eq!(filters.apply("B", &EMA, 1.0, 0), 1.0);
+        assert_eq!(filters.apply("A", &EMA, 1.0, 16), 0.5);
+    }
+
+    #[test]
+    fn validates_settings() {
+        assert!(EMA.validate().is_ok());
+        assert!(FilterConfig::Ema { alpha: 0.0 }.validate().is_err());
+        assert!(FilterConfig::Ema { alpha: f64::NAN }.validate().is_err());
+        assert!(FilterConfig::OneEuro {
+            min_cutoff: 1.0,
+            beta: -1.0,
+            d_cutoff: 1.0
+        }
+        .validate()
+        .is_err());
+        assert!(FilterConfig::Kalman {
+            process_noise: 0.0,
+            measurement_noise: 1.0
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use evalexpr::{
+    ContextWithMutableFunctions, EvalexprError, EvalexprResult, FloatType, Function,
+    HashMapContext, Value,
+};
+
+type Builtin = fn(&Value) -> EvalexprResult<Value>;
+
+// Overrides builtin min and max, which return an int when it's the biggest one and accept only 2+ arguments
+const FUNCTIONS: [(&str, Builtin); 9] = [
+    ("remap", remap),
+    ("clamp", clamp),
+    ("deadzone", deadzone),
+    ("smoothstep", smoothstep),
+    ("lerp", lerp),
+    ("curve", curve),
+    ("sign", sign),
+    ("min", min),
+    ("max", max),
+];
+
+// Builtins of evalexpr 11, random is there with the rand feature enabled by the bridge
+const BUILTINS: [&str; 50] = [
+    "math::ln",
+    "math::log",
+    "math::log2",
+    "math::log10",
+    "math::exp",
+    "math::exp2",
+    "math::pow",
+    "math::cos",
+    "math::acos",
+    "math::cosh",
+    "math::acosh",
+    "math::sin",
+    "math::asin",
+    "math::sinh",
+    "math::asinh",
+    "math::tan",
+    "math::atan",
+    "math::tanh",
+    "math::atanh",
+    "math::atan2",
+    "math::sqrt",
+    "math::cbrt",
+    "math::hypot",
+    "floor",
+    "round",
+    "ceil",
+    "math::is_nan",
+    "math::is_finite",
+    "math::is_infinite",
+    "math::is_normal",
+    "math::abs",
+    "typeof",
+    "min",
+    "max",
+    "if",
+    "contains",
+    "contains_any",
+    "len",
+    "str::to_lowercase",
+    "str::to_uppercase",
+    "str::trim",
+    "str::from",
+    "str::substring",
+    "random",
+    "bitand",
+    "bitor",
+    "bitxor",
+    "bitnot",
+    "shl",
+    "shr",
+];
+
+pub fn insert_functions(context: &mut HashMapContext) {
+    for (name, function) in FUNCTIONS {
+        context
+            .set_function(name.to_string(), Function::new(function))
+            .unwrap();
+    }
+}
+
+// Functions of the bridge and builtins of evalexpr
+pub fn is_function(name: &str) -> bool {
+    FUNCTIONS.iter().any(|(function, _)| *function == name) || BUILTINS.contains(&name)
+}
+
+// Ints and floats as floats, so "remap(x, 0, 1, 0, 100)" works
+fn numbers<const N: usize>(argument: &Value) -> EvalexprResult<[FloatType; N]> {
+    let mut numbers = [0.0; N];
+    for (number, value) in numbers.iter_mut().zip(argument.as_fixed_len_tuple(N)?) {
+        *number = value.as_number()?;
+    }
+    Ok(numbers)
+}
+
+// One number or a tuple of them
+fn all_numbers(argument: &Value) -> EvalexprResult<Vec<FloatType>> {
+    match argument {
+        Value::Tuple(values) => values.iter().map(Value::as_number).collect(),
+        value => Ok(vec![value.as_number()?]),
+    }
+}
+
+// Not clamped: remap(1.5, 0, 1, 0, 10) is 15
+fn remap(argument: &Value) -> EvalexprResult<Value> {
+    let [x, in_min, in_max, out_min, out_max] = numbers(argument)?;
+    if in_min == in_max {
+        return Err(EvalexprError::CustomMessage(
+            "remap input range is empty".into(),
+        ));
+    }
+    Ok((out_min + (x - in_min) * (out_max - out_min) / (in_max - in_min)).into())
+}
+
+fn clamp(argument: &Value) -> EvalexprResult<Value> {
+    let [x, min, max] = numbers(argument)?;
+    Ok(x.max(min).min(max).into())
+}
+
+// 0 within d of 0, outside it moves towards 0 by d so there is no jump
+fn deadzone(argument: &Value) -> EvalexprResult<Value> {
+    let [x, d] = numbers(argument)?;
+    Ok(if x.abs() <= d { 0.0 } else { x - d.copysign(x) }.into())
+}
+
+// Same argument order as GLSL: smoothstep(edge0, edge1, x)
+fn smoothstep(argument: &Value) -> EvalexprResult<Value> {
+    let [edge0, edge1, x] = numbers(argument)?;
+    if edge0 == edge1 {
+        return Ok(if x < edge0 { 0.0 } else { 1.0 }.into());
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    Ok((t * t * (3.0 - 2.0 * t)).into())
+}
+
+fn lerp(argument: &Value) -> EvalexprResult<Value> {
+    let [a, b, t] = numbers(argument)?;
+    Ok((a + (b - a) * t).into())
+}
+
+// Easing of x in 0...1, values outside are clamped
+fn curve(argument: &Value) -> EvalexprResult<Value> {
+    let arguments = argument.as_fixed_len_tuple(2)?;
+    let x = arguments[0].as_number()?.clamp(0.0, 1.0);
+    let value = match arguments[1].as_string()?.as_str() {
+        "linear" => x,
+        "easeIn" => x * x,
+        "easeOut" => 1.0 - (1.0 - x) * (1.0 - x),
+        "easeInOut" if x < 0.5 => 2.0 * x * x,
+        "easeInOut" => 1.0 - 2.0 * (1.0 - x) * (1.0 - x),
+        "easeInCubic" => x * x * x,
+        "easeOutCubic" => 1.0 - (1.0 - x).powi(3),
+        "easeInOutCubic" if x < 0.5 => 4.0 * x * x * x,
+        "easeInOutCubic" => 1.0 - 4.0 * (1.0 - x).powi(3),
+        name => {
+            return Err(EvalexprError::CustomMessage(format!(
+                "Unknown curve \"{}\", curves are linear, easeIn, easeOut, easeInOut, easeInCubic, easeOutCubic and easeInOutCubic",
+                name
+            )))
+        }
+    };
+    Ok(value.into())
+}
+
+// 0 for 0, unlike f64::signum
+fn sign(argument: &Value) -> EvalexprResult<Value> {
+    let x = argument.as_number()?;
+    Ok(if x == 0.0 { 0.0 } else { x.signum() }.into())
+}
+
+fn min(argument: &Value) -> EvalexprResult<Value> {
+    let numbers = all_numbers(argument)?;
+    Ok(numbers
+        .into_iter()
+        .fold(FloatType::INFINITY, FloatType::min)
+        .into())
+}
+
+fn max(argument: &Value) -> EvalexprResult<Value> {
+    let numbers = all_numbers(argument)?;
+    Ok(numbers
+        .into_iter()
+        .fold(FloatType::NEG_INFINITY, FloatType::max)
+        .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use evalexpr::{eval, eval_with_context};
+
+    use super::*;
+
+    fn number(expression: &str) -> FloatType {
+        let mut context = HashMapContext::new();
+        insert_functions(&mut context);
+        match eval_with_context(expression, &context) {
+            Ok(Value::Float(value)) => value,
+            result => panic!("{expression}: {result:?}"),
+        }
+    }
+
+    fn fails(expression: &str) -> bool {
+        let mut context = HashMapContext::new();
+        insert_functions(&mut context);
+        eval_with_context(expression, &context).is_err()
+    }
+
+    #[test]
+    fn remaps_ranges() {
+        assert_eq!(number("remap(0.5, 0, 1, 0, 100)"), 50.0);
+        assert_eq!(number("remap(1.5, 0, 1, 0, 10)"), 15.0);
+        assert_eq!(number("remap(0.25, 1, 0, 0, 1)"), 0.75);
+        assert_eq!(number("remap(2, 1, 3, 10, 20)"), 15.0);
+        assert!(fails("remap(0.5, 1, 1, 0, 10)"));
+        assert!(fails("remap(0.5, 0, 1)"));
+    }
+
+    #[test]
+    fn clamps_and_interpolates() {
+        assert_eq!(number("clamp(1.5, 0, 1)"), 1.0);
+        assert_eq!(number("clamp(-2, -1, 1)"), -1.0);
+        assert_eq!(number("clamp(0.5, 0, 1)"), 0.5);
+        assert_eq!(number("lerp(10, 20, 0.25)"), 12.5);
+        assert_eq!(number("lerp(1, 3, 2)"), 5.0);
+        assert!(fails("clamp(\"a\", 0, 1)"));
+    }
+
+    #[test]
+    fn applies_deadzone() {
+        assert_eq!(number("deadzone(0.05, 0.1)"), 0.0);
+        assert_eq!(number("deadzone(0.1, 0.1)"), 0.0);
+        assert_eq!(number("deadzone(-0.1, 0.1)"), 0.0);
+        assert!((number("deadzone(0.3, 0.1)") - 0.2).abs() < 1e-12);
+        assert!((number("deadzone(-0.3, 0.1)") + 0.2).abs() < 1e-12);
+        assert_eq!(number("deadzone(3, 1)"), 2.0);
+    }
+
+    #[test]
+    fn smooths_steps() {
+        assert_eq!(number("smoothstep(0, 1, -1)"), 0.0);
+        assert_eq!(number("smoothstep(0, 1, 0.5)"), 0.5);
+        assert_eq!(number("smoothstep(0, 1, 2)"), 1.0);
+        assert_eq!(number("smoothstep(0, 2, 1)"), 0.5);
+        // Empty range is a step
+        assert_eq!(number("smoothstep(0.5, 0.5, 0.4)"), 0.0);
+        assert_eq!(number("smoothstep(0.5, 0.5, 0.5)"), 1.0);
+    }
+
+    #[test]
+    fn eases_curves() {
+        assert_eq!(number("curve(0.5, \"linear\")"), 0.5);
+        assert_eq!(number("curve(0.5, \"easeIn\")"), 0.25);
+        assert_eq!(number("curve(0.5, \"easeOut\")"), 0.75);
+        assert_eq!(number("curve(0.25, \"easeInOut\")"), 0.125);
+        assert_eq!(number("curve(0.75, \"easeInOut\")"), 0.875);
+        assert_eq!(number("curve(0.5, \"easeInCubic\")"), 0.125);
+        assert_eq!(number("curve(0.5, \"easeOutCubic\")"), 0.875);
+        assert_eq!(number("curve(0.25, \"easeInOutCubic\")"), 0.0625);
+        assert_eq!(number("curve(1, \"easeInOutCubic\")"), 1.0);
+        assert_eq!(number("curve(2, \"easeIn\")"), 1.0);
+        assert_eq!(number("curve(-1, \"easeOut\")"), 0.0);
+        assert!(fails("curve(0.5, \"bounce\")"));
+        assert!(fails("curve(0.5, 1)"));
+    }
+
+    #[test]
+    fn signs_zero_as_zero() {
+        assert_eq!(number("sign(-0.5)"), -1.0);
+        assert_eq!(number("sign(3)"), 1.0);
+        assert_eq!(number("sign(0)"), 0.0);
+        assert_eq!(number("sign(0.0)"), 0.0);
+    }
+
+    #[test]
+    fn returns_floats_from_min_and_max() {
+        // Builtins would return the int
+        assert_eq!(number("min(1, 0.5)"), 0.5);
+        assert_eq!(number("max(1, 0.5)"), 1.0);
+        assert_eq!(number("max(2)"), 2.0);
+        assert_eq!(number("min(3, -1, 2)"), -1.0);
+        assert_eq!(number("max(0.1, 0.2, 0.3, 0.4)"), 0.4);
+        assert!(fails("max(1, \"a\")"));
+    }
+
+    #[test]
+    fn lists_builtins_of_evalexpr() {
+        // random is missing without the rand feature
+        for name in BUILTINS.iter().filter(|name| **name != "random") {
+            assert!(
+                !matches!(
+                    eval(&format!("{name}(())")),
+                    Err(EvalexprError::FunctionIdentifierNotFound(_))
+                ),
+                "{name}"
+            );
+        }
+        assert!(is_function("remap") && is_function("math::sin") && is_function("random"));
+        assert!(!is_function("smooth") && !is_function("sin"));
+    }
+}
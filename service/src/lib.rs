@@ -1,3 +1,12 @@
+pub mod calibration;
+pub mod curve;
+pub mod error;
+pub mod filter;
+pub mod functions;
 pub mod utils;
+pub mod bridge;
+pub mod check;
+pub mod recording;
+pub mod transform;
 pub mod tracking;
 pub mod vts;
@@ -0,0 +1,275 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::{
+    tracking::response::TrackingResponse,
+    utils::{get_current_timestamp, get_current_timestamp_ms},
+};
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum RecordingFormat {
+    // One JSON object per line
+    #[default]
+    JsonLines,
+    // Records encoded with bincode one after another
+    Binary,
+}
+
+impl RecordingFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordingFormat::JsonLines => "jsonl",
+            RecordingFormat::Binary => "bin",
+        }
+    }
+
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("bin") => RecordingFormat::Binary,
+            _ => RecordingFormat::JsonLines,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Record {
+    // Name of the tracking source, like "ifm" or "vmc2"
+    pub source: String,
+    pub received_ms: u64,
+    pub response: TrackingResponse,
+}
+
+pub struct Recorder {
+    format: RecordingFormat,
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    // Creates "recording_<timestamp>.<extension>" in the directory
+    pub fn create(directory: &str, format: RecordingFormat) -> std::io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        let path = Path::new(directory).join(format!(
+            "recording_{}.{}",
+            get_current_timestamp(),
+            format.extension()
+        ));
+        let writer = BufWriter::new(File::create(&path)?);
+        info!("Recording tracking data to {}", path.display());
+
+        Ok(Self { format, writer })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Box<dyn std::error::Error>> {
+        match self.format {
+            RecordingFormat::JsonLines => {
+                serde_json::to_writer(&mut self.writer, record)?;
+                self.writer.write_all(b"\n")?;
+            }
+            RecordingFormat::Binary => bincode::serialize_into(&mut self.writer, record)?,
+        }
+        // Recording should survive a crash, that's when it's needed the most
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    // Writes every response of the source and passes it further to the plugin
+    pub fn tee(
+        recorder: Arc<Mutex<Recorder>>,
+        source: String,
+        receiver: Receiver<TrackingResponse>,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        while active.load(Ordering::Relaxed) {
+            match receiver.recv_timeout(Duration::from_secs(2)) {
+                Ok(response) => {
+                    let record = Record {
+                        source: source.clone(),
+                        received_ms: get_current_timestamp_ms() as u64,
+                        response,
+                    };
+                    if let Err(error) = recorder.lock().unwrap().write(&record) {
+                        warn!("Unable to record tracking data: {}", error);
+                    }
+                    if sender.send(record.response).is_err() {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+}
+
+// Reads records one by one, recordings can be too big to load at once
+pub struct RecordReader {
+    format: RecordingFormat,
+    reader: BufReader<File>,
+}
+
+impl RecordReader {
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            format: RecordingFormat::from_path(path),
+            reader: BufReader::new(File::open(path)?),
+        })
+    }
+
+    // None at the end of the recording
+    pub fn read(&mut self) -> Result<Option<Record>, Box<dyn std::error::Error>> {
+        match self.format {
+            RecordingFormat::JsonLines => {
+                let mut line = String::new();
+                while line.trim().is_empty() {
+                    line.clear();
+                    if self.reader.read_line(&mut line)? == 0 {
+                        return Ok(None);
+                    }
+                }
+                Ok(Some(serde_json::from_str(&line)?))
+            }
+            RecordingFormat::Binary => match bincode::deserialize_from(&mut self.reader) {
+                Ok(record) => Ok(Some(record)),
+                // Last record can be cut off when the recording was interrupted
+                Err(error) => match *error {
+                    bincode::ErrorKind::Io(ref io_error)
+                        if io_error.kind() == ErrorKind::UnexpectedEof =>
+                    {
+                        Ok(None)
+                    }
+                    _ => Err(error),
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::tracking::response::{Cords, Shape};
+
+    fn record(source: &str, received_ms: u64, jaw_open: f64) -> Record {
+        Record {
+            source: source.into(),
+            received_ms,
+            response: TrackingResponse {
+                timestamp: received_ms,
+                hotkey: -1,
+                face_found: true,
+                rotation: Cords {
+                    x: 1.5,
+                    y: -2.0,
+                    z: 0.25,
+                },
+                position: Cords::default(),
+                eye_left: Cords::default(),
+                blend_shapes: vec![Shape {
+                    k: "JawOpen".into(),
+                    v: jaw_open,
+                }],
+            },
+        }
+    }
+
+    // Records to an empty directory and returns the path of the recording
+    fn write_recording(name: &str, format: RecordingFormat, records: &[Record]) -> String {
+        let directory = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&directory);
+        let mut recorder = Recorder::create(directory.to_str().unwrap(), format).unwrap();
+        for record in records {
+            recorder.write(record).unwrap();
+        }
+
+        let path = fs::read_dir(&directory)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        assert_eq!(RecordingFormat::from_path(path.to_str().unwrap()), format);
+        path.display().to_string()
+    }
+
+    fn read_all(path: &str) -> Vec<Record> {
+        let mut reader = RecordReader::open(path).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = reader.read().unwrap() {
+            records.push(record);
+        }
+        records
+    }
+
+    fn assert_records(records: &[Record]) {
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].source, "ifm");
+        assert_eq!(records[1].source, "vmc2");
+        assert_eq!(records[1].received_ms, 1020);
+        let response = &records[1].response;
+        assert_eq!((response.timestamp, response.hotkey), (1020, -1));
+        assert!(response.face_found);
+        assert_eq!(response.rotation.x, 1.5);
+        assert_eq!(response.rotation.y, -2.0);
+        assert_eq!(response.blend_shapes[0].k, "JawOpen");
+        assert_eq!(response.blend_shapes[0].v, 0.125);
+    }
+
+    #[test]
+    fn reads_back_json_lines() {
+        let records = [record("ifm", 1000, 0.5), record("vmc2", 1020, 0.125)];
+        let path = write_recording(
+            "sandoitchi_recording_jsonl",
+            RecordingFormat::JsonLines,
+            &records,
+        );
+        assert!(path.ends_with(".jsonl"));
+
+        // Blank lines are skipped
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"\n\n").unwrap();
+        assert_records(&read_all(&path));
+    }
+
+    #[test]
+    fn reads_back_binary_without_cut_off_record() {
+        let records = [record("ifm", 1000, 0.5), record("vmc2", 1020, 0.125)];
+        let path = write_recording(
+            "sandoitchi_recording_bin",
+            RecordingFormat::Binary,
+            &records,
+        );
+        assert!(path.ends_with(".bin"));
+        assert_records(&read_all(&path));
+
+        // Recording interrupted in the middle of the third record
+        let third = bincode::serialize(&record("ifm", 1040, 1.0)).unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&third[..third.len() / 2]).unwrap();
+        assert_records(&read_all(&path));
+    }
+
+    #[test]
+    fn rejects_broken_json_lines() {
+        let path = env::temp_dir().join("sandoitchi_recording_broken.jsonl");
+        fs::write(&path, "{\"source\": \"ifm\"\n").unwrap();
+        let mut reader = RecordReader::open(path.to_str().unwrap()).unwrap();
+        assert!(reader.read().is_err());
+    }
+}
@@ -1,4 +1,12 @@
 pub mod response;
+pub mod arkit;
 pub mod client;
+pub mod udp;
 pub mod ifacialmocap;
-pub mod vtubestudio;
\ No newline at end of file
+pub mod vtubestudio;
+pub mod vmc;
+pub mod openseeface;
+pub mod facemotion3d;
+pub mod meowface;
+pub mod livelinkface;
+pub mod replay;
\ No newline at end of file
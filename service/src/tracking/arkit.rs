@@ -0,0 +1,74 @@
+use crate::utils::normalize_shape_name;
+
+pub const BLEND_SHAPES: [&str; 52] = [
+    "BrowDownLeft",
+    "BrowDownRight",
+    "BrowInnerUp",
+    "BrowOuterUpLeft",
+    "BrowOuterUpRight",
+    "CheekPuff",
+    "CheekSquintLeft",
+    "CheekSquintRight",
+    "EyeBlinkLeft",
+    "EyeBlinkRight",
+    "EyeLookDownLeft",
+    "EyeLookDownRight",
+    "EyeLookInLeft",
+    "EyeLookInRight",
+    "EyeLookOutLeft",
+    "EyeLookOutRight",
+    "EyeLookUpLeft",
+    "EyeLookUpRight",
+    "EyeSquintLeft",
+    "EyeSquintRight",
+    "EyeWideLeft",
+    "EyeWideRight",
+    "JawForward",
+    "JawLeft",
+    "JawOpen",
+    "JawRight",
+    "MouthClose",
+    "MouthDimpleLeft",
+    "MouthDimpleRight",
+    "MouthFrownLeft",
+    "MouthFrownRight",
+    "MouthFunnel",
+    "MouthLeft",
+    "MouthLowerDownLeft",
+    "MouthLowerDownRight",
+    "MouthPressLeft",
+    "MouthPressRight",
+    "MouthPucker",
+    "MouthRight",
+    "MouthRollLower",
+    "MouthRollUpper",
+    "MouthShrugLower",
+    "MouthShrugUpper",
+    "MouthSmileLeft",
+    "MouthSmileRight",
+    "MouthStretchLeft",
+    "MouthStretchRight",
+    "MouthUpperUpLeft",
+    "MouthUpperUpRight",
+    "NoseSneerLeft",
+    "NoseSneerRight",
+    "TongueOut",
+];
+
+// "eyeblink_l", "EYE_BLINK_LEFT" and "eyeBlinkLeft" all become "EyeBlinkLeft"
+pub fn canonical_shape_name(name: &str) -> String {
+    let lowercase = name.to_ascii_lowercase();
+    let key = if let Some(base) = lowercase.strip_suffix("_l") {
+        format!("{base}left")
+    } else if let Some(base) = lowercase.strip_suffix("_r") {
+        format!("{base}right")
+    } else {
+        lowercase
+    }
+    .replace('_', "");
+
+    BLEND_SHAPES
+        .iter()
+        .find(|shape| shape.eq_ignore_ascii_case(&key))
+        .map_or_else(|| normalize_shape_name(name), |shape| shape.to_string())
+}
@@ -5,14 +5,71 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
-use crate::tracking::response::TrackingResponse;
+use crate::tracking::{
+    facemotion3d::Facemotion3dTrackingClient,
+    ifacialmocap::{IFacialMocapFormat, IFacialMocapTrackingClinet, IFacialMocapTransport},
+    livelinkface::LiveLinkFaceTrackingClient,
+    meowface::MeowFaceTrackingClient,
+    openseeface::OpenSeeFaceTrackingClient,
+    replay::ReplayTrackingClient,
+    response::TrackingResponse,
+    vmc::VmcTrackingClient,
+    vtubestudio::VTubeStudioTrackingClient,
+};
+
+// Client specific settings, each client reads only its own fields
+#[derive(Debug, Clone)]
+pub struct TrackingOptions {
+    pub ifm_transport: IFacialMocapTransport,
+    pub ifm_format: IFacialMocapFormat,
+    // Another one when the phone simulator runs on the same PC, which has 49983 taken in UDP mode
+    pub ifm_phone_port: u16,
+    // 0 is any free port
+    pub vts_listen_port: u16,
+    pub replay_file: String,
+    pub replay_speed: f64,
+    pub replay_loop: bool,
+    // Milliseconds from the beginning of the recording
+    pub replay_start: u64,
+    // Empty is the first source in the recording
+    pub replay_source: String,
+}
+
+impl Default for TrackingOptions {
+    fn default() -> Self {
+        Self {
+            ifm_transport: IFacialMocapTransport::default(),
+            ifm_format: IFacialMocapFormat::default(),
+            ifm_phone_port: IFacialMocapTrackingClinet::PHONE_PORT,
+            vts_listen_port: 0,
+            replay_file: String::new(),
+            replay_speed: 1.0,
+            replay_loop: false,
+            replay_start: 0,
+            replay_source: String::new(),
+        }
+    }
+}
+
+pub type TrackingClientFn = fn(
+    ip: String,
+    options: TrackingOptions,
+    sender: Sender<TrackingResponse>,
+    active: Arc<AtomicBool>,
+);
 
 pub trait TrackingClient {
-    fn run(ip: String, sender: Sender<TrackingResponse>, active: Arc<AtomicBool>);
+    fn run(
+        ip: String,
+        options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    );
 
     // Something like middleware
     fn send(sender: &Sender<TrackingResponse>, response: TrackingResponse) {
-        sender.send(response).unwrap();
+        // Receiver is gone only when the bridge is stopping, the client finishes on its own
+        let _ = sender.send(response);
     }
 }
 
@@ -21,6 +78,52 @@ pub enum TrackingClientType {
     #[default]
     VTubeStudio,
     IFacialMocap,
+    Vmc,
+    OpenSeeFace,
+    Facemotion3d,
+    MeowFace,
+    LiveLinkFace,
+    Replay,
+}
+
+impl TrackingClientType {
+    pub const ALL: [TrackingClientType; 8] = [
+        TrackingClientType::VTubeStudio,
+        TrackingClientType::IFacialMocap,
+        TrackingClientType::Vmc,
+        TrackingClientType::OpenSeeFace,
+        TrackingClientType::Facemotion3d,
+        TrackingClientType::MeowFace,
+        TrackingClientType::LiveLinkFace,
+        TrackingClientType::Replay,
+    ];
+
+    // Used as a prefix of variables when several clients run at once
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            TrackingClientType::VTubeStudio => "vts",
+            TrackingClientType::IFacialMocap => "ifm",
+            TrackingClientType::Vmc => "vmc",
+            TrackingClientType::OpenSeeFace => "osf",
+            TrackingClientType::Facemotion3d => "fm3d",
+            TrackingClientType::MeowFace => "meow",
+            TrackingClientType::LiveLinkFace => "llf",
+            TrackingClientType::Replay => "replay",
+        }
+    }
+
+    pub fn client_fn(&self) -> TrackingClientFn {
+        match self {
+            TrackingClientType::VTubeStudio => VTubeStudioTrackingClient::run,
+            TrackingClientType::IFacialMocap => IFacialMocapTrackingClinet::run,
+            TrackingClientType::Vmc => VmcTrackingClient::run,
+            TrackingClientType::OpenSeeFace => OpenSeeFaceTrackingClient::run,
+            TrackingClientType::Facemotion3d => Facemotion3dTrackingClient::run,
+            TrackingClientType::MeowFace => MeowFaceTrackingClient::run,
+            TrackingClientType::LiveLinkFace => LiveLinkFaceTrackingClient::run,
+            TrackingClientType::Replay => ReplayTrackingClient::run,
+        }
+    }
 }
 
 impl Display for TrackingClientType {
@@ -28,6 +131,12 @@ impl Display for TrackingClientType {
         match self {
             TrackingClientType::VTubeStudio => write!(f, "VTubeStudio"),
             TrackingClientType::IFacialMocap => write!(f, "iFacialMocap"),
+            TrackingClientType::Vmc => write!(f, "VMC"),
+            TrackingClientType::OpenSeeFace => write!(f, "OpenSeeFace"),
+            TrackingClientType::Facemotion3d => write!(f, "Facemotion3d"),
+            TrackingClientType::MeowFace => write!(f, "MeowFace"),
+            TrackingClientType::LiveLinkFace => write!(f, "Live Link Face"),
+            TrackingClientType::Replay => write!(f, "Replay"),
         }
     }
 }
\ No newline at end of file
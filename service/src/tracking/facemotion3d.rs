@@ -0,0 +1,96 @@
+use std::sync::{atomic::AtomicBool, mpsc::Sender, Arc};
+
+use crate::{
+    error::BridgeError,
+    tracking::{
+        client::{TrackingClient, TrackingOptions},
+        ifacialmocap::parse_delimited_tracking_string,
+        response::TrackingResponse,
+        udp::UdpStream,
+    },
+};
+
+pub struct Facemotion3dTrackingClient;
+
+impl Facemotion3dTrackingClient {
+    const PHONE_PORT: u16 = 49993;
+    const UDP_PORT: u16 = 50003;
+    const START_MESSAGE: &str = "FACEMOTION3D_OtherStreaming";
+    const STOP_MESSAGE: &str = "StopStreaming_FACEMOTION3D";
+
+    // Same layout as iFacialMocap, but "key&value" instead of "key-value"
+    fn parse(data: &str) -> Result<TrackingResponse, BridgeError> {
+        parse_delimited_tracking_string(data, '&')
+            .map_err(|error| BridgeError::Protocol(format!("Unable to parse frame: {}", error)))
+    }
+}
+
+impl TrackingClient for Facemotion3dTrackingClient {
+    fn run(
+        ip: String,
+        _options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        let stream = UdpStream {
+            local_port: Self::UDP_PORT,
+            destination_address: format!("{}:{}", ip, Self::PHONE_PORT),
+            start_message: Self::START_MESSAGE,
+            stop_message: Some(Self::STOP_MESSAGE),
+        };
+        stream.run::<Self>(Self::parse, &sender, &active);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(response: &TrackingResponse, name: &str) -> f64 {
+        response
+            .blend_shapes
+            .iter()
+            .find(|shape| shape.k == name)
+            .unwrap()
+            .v
+    }
+
+    #[test]
+    fn parses_frames_with_ampersand_values() {
+        let response = Facemotion3dTrackingClient::parse(
+            "eyeBlink_R&80|jawOpen&25|=head#5,-7.5,1,0.5,1.5,-2|rightEye#1,2,3|leftEye#4,5,6|",
+        )
+        .unwrap();
+        assert!(response.face_found);
+        assert_eq!(shape(&response, "EyeBlinkRight"), 0.8);
+        assert_eq!(shape(&response, "JawOpen"), 0.25);
+        assert_eq!(
+            (
+                response.rotation.x,
+                response.rotation.y,
+                response.rotation.z
+            ),
+            (-7.5, 5.0, 1.0)
+        );
+        assert_eq!(response.position.z, -2.0);
+        assert_eq!(shape(&response, "LeftEyeZ"), 6.0);
+    }
+
+    #[test]
+    fn rejects_frames_of_other_apps() {
+        // Values with the iFacialMocap separator are skipped
+        let response = Facemotion3dTrackingClient::parse(
+            "jawOpen-25|=head#0,0,0,0,0,0|rightEye#0,0,0|leftEye#0,0,0|",
+        )
+        .unwrap();
+        assert!(response
+            .blend_shapes
+            .iter()
+            .all(|shape| shape.k != "JawOpen"));
+        assert!(Facemotion3dTrackingClient::parse("jawOpen&25|").is_err());
+        assert!(Facemotion3dTrackingClient::parse(
+            "jawOpen&x|=head#0,0,0,0,0,0|rightEye#0,0,0|leftEye#0,0,0|"
+        )
+        .is_err());
+    }
+}
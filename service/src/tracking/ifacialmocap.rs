@@ -1,102 +1,258 @@
 use crate::{
+    error::BridgeError,
     tracking::{
-        client::TrackingClient,
+        client::{TrackingClient, TrackingOptions},
         response::{Cords, Shape, TrackingResponse},
+        udp::UdpStream,
     },
-    utils::get_current_timestamp,
+    utils::{get_current_timestamp, normalize_shape_name},
 };
+use log::{error, info, warn};
 use regex::Regex;
-use std::{collections::HashMap, sync::atomic::Ordering};
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, LazyLock},
+};
 
 use std::{
-    io::Read,
+    io::{ErrorKind, Read},
     net::{TcpListener, UdpSocket},
     sync::{atomic::AtomicBool, mpsc::Sender, Arc},
     thread, time,
 };
 
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum IFacialMocapTransport {
+    // Phone connects to the PC and streams over TCP
+    #[default]
+    Tcp,
+    // Phone sends one UDP packet per frame
+    Udp,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum IFacialMocapFormat {
+    // "key-value|...|=head#...|rightEye#...|leftEye#..."
+    #[default]
+    Text,
+    Json,
+}
+
 #[derive(Clone)]
 pub struct IFacialMocapTrackingClinet;
 
-impl TrackingClient for IFacialMocapTrackingClinet {
-    fn run(ip: String, sender: Sender<TrackingResponse>, active: Arc<AtomicBool>) {
+impl IFacialMocapTrackingClinet {
+    pub const PHONE_PORT: u16 = 49983;
+    const UDP_PORT: u16 = 49983;
+    const TCP_PORT: u16 = 49986;
+
+    fn handshake_message(transport: IFacialMocapTransport, format: IFacialMocapFormat) -> String {
+        let message = match transport {
+            IFacialMocapTransport::Tcp => {
+                "iFacialMocap_UDPTCP_sahuasouryya9218sauhuiayeta91555dy3719"
+            }
+            IFacialMocapTransport::Udp => "iFacialMocap_sahuasouryya9218sauhuiayeta91555dy3719",
+        };
+        match format {
+            IFacialMocapFormat::Text => message.to_string(),
+            IFacialMocapFormat::Json => format!("{}|json", message),
+        }
+    }
+
+    fn parse(string: &str, format: IFacialMocapFormat) -> Result<TrackingResponse, BridgeError> {
+        match format {
+            IFacialMocapFormat::Text => parse_tracking_string(string),
+            IFacialMocapFormat::Json => parse_tracking_json(string),
+        }
+        .map_err(|error| BridgeError::Protocol(error.to_string()))
+    }
+
+    fn run_tcp(
+        ip: String,
+        phone_port: u16,
+        format: IFacialMocapFormat,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
         // UDP connection
-        let udp_thread = thread::spawn(move || {
-            let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let udp_thread = thread::spawn(move || -> std::io::Result<()> {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
             let _ = socket.set_read_timeout(Some(time::Duration::new(2, 0)));
-            let message = "iFacialMocap_UDPTCP_sahuasouryya9218sauhuiayeta91555dy3719";
+            let message = Self::handshake_message(IFacialMocapTransport::Tcp, format);
 
-            let destination_address = format!("{}:{}", ip, 49983);
-            socket
-                .send_to(message.as_bytes(), &destination_address)
-                .expect("Failed to send UDP message");
-            println!("UDP message sent to {}", destination_address);
+            let destination_address = format!("{}:{}", ip, phone_port);
+            socket.send_to(message.as_bytes(), &destination_address)?;
+            info!("UDP message sent to {}", destination_address);
+            Ok(())
         });
-        udp_thread.join().expect("UDP thread panicked");
+        // Phone may still connect on its own, so the server is started anyway
+        if let Ok(Err(error)) = udp_thread.join() {
+            warn!("Unable to send UDP message: {}", error);
+        }
 
         // TCP Server
-        let tcp_server_thread = thread::spawn(move || {
-            let address = format!("{}:{}", "0.0.0.0", 49986);
-            let listener = TcpListener::bind(&address).unwrap();
-            println!("TCP server listening on {address}");
-
-            for stream in listener.incoming() {
-                if !active.load(Ordering::Relaxed) {
-                    return;
-                }
+        let address = format!("{}:{}", "0.0.0.0", Self::TCP_PORT);
+        // Not blocking, so the server notices when it's stopped
+        let listener = match TcpListener::bind(&address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+        {
+            Ok(listener) => listener,
+            Err(error) => {
+                error!("Unable to listen on {}: {}", address, error);
+                return;
+            }
+        };
+        info!("TCP server listening on {address}");
+
+        let mut connections = Vec::new();
+        while active.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((mut stream, _address)) => {
+                    let _ = stream.set_nonblocking(false);
+                    let _ = stream.set_read_timeout(Some(time::Duration::new(2, 0)));
+                    let sender_clone = sender.clone();
+                    let active_clone = Arc::clone(&active);
+                    connections.push(thread::spawn(move || {
+                        let mut partial_buffer = String::new();
+                        let mut buffer = [0; 8192];
+
+                        while active_clone.load(Ordering::Relaxed) {
+                            match &stream.read(&mut buffer) {
+                                Ok(0) => {
+                                    info!("Connection closed by the phone");
+                                    break;
+                                }
+                                Ok(n) => {
+                                    if let Ok(raw_data) = String::from_utf8(buffer[..*n].to_vec()) {
+                                        partial_buffer.push_str(&raw_data);
 
-                match stream {
-                    Ok(mut stream) => {
-                        let sender_clone = sender.clone();
-                        thread::spawn(move || {
-                            let mut partial_buffer = String::new();
-                            let mut buffer = [0; 8192];
-
-                            loop {
-                                match &stream.read(&mut buffer) {
-                                    Ok(n) => {
-                                        if let Ok(raw_data) =
-                                            String::from_utf8(buffer[..*n].to_vec())
-                                        {
-                                            partial_buffer.push_str(&raw_data);
-
-                                            let re =
-                                                Regex::new("___iFacialMocaptrackingStatus-[01]\\|")
-                                                    .unwrap();
-                                            let mut matches: Vec<_> =
-                                                re.find_iter(&partial_buffer).collect();
-                                            while matches.len() >= 2 {
-                                                let first_start = matches[0].start();
-                                                let second_start = matches[1].start();
-
-                                                let data_to_parse =
-                                                    &partial_buffer[first_start..second_start];
-                                                let tracking_response =
-                                                    parse_tracking_string(&data_to_parse).unwrap();
-
-                                                Self::send(&sender_clone, tracking_response);
-
-                                                partial_buffer.replace_range(0..second_start, "");
-                                                matches = re.find_iter(&partial_buffer).collect();
+                                        let frames = match format {
+                                            IFacialMocapFormat::Text => {
+                                                split_text_frames(&mut partial_buffer)
+                                            }
+                                            IFacialMocapFormat::Json => {
+                                                split_json_frames(&mut partial_buffer)
+                                            }
+                                        };
+                                        // Malformed frame is skipped, the stream goes on
+                                        for data_to_parse in frames {
+                                            match Self::parse(&data_to_parse, format) {
+                                                Ok(tracking_response) => {
+                                                    Self::send(&sender_clone, tracking_response)
+                                                }
+                                                Err(error) => warn!("{}", error),
                                             }
                                         }
                                     }
-                                    Err(e) => {
-                                        eprintln!("Failed to read from socket; err = {:?}", e);
-                                        break;
-                                    }
+                                }
+                                Err(e)
+                                    if matches!(
+                                        e.kind(),
+                                        ErrorKind::WouldBlock | ErrorKind::TimedOut
+                                    ) => {}
+                                Err(e) => {
+                                    warn!("Failed to read from socket: {}", e);
+                                    break;
                                 }
                             }
-                        });
-                    }
-                    Err(e) => eprintln!("Failed to accept connection; err = {:?}", e),
+                        }
+                    }));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(time::Duration::from_millis(100));
                 }
+                Err(e) => warn!("Failed to accept connection: {}", e),
             }
-        });
-        tcp_server_thread
-            .join()
-            .expect("TCP server thread panicked");
+        }
+
+        for connection in connections {
+            let _ = connection.join();
+        }
+    }
+
+    fn run_udp(
+        ip: String,
+        phone_port: u16,
+        format: IFacialMocapFormat,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        let stream = UdpStream {
+            local_port: Self::UDP_PORT,
+            destination_address: format!("{}:{}", ip, phone_port),
+            start_message: &Self::handshake_message(IFacialMocapTransport::Udp, format),
+            stop_message: None,
+        };
+        stream.run::<Self>(|data| Self::parse(data, format), &sender, &active);
+    }
+}
+
+impl TrackingClient for IFacialMocapTrackingClinet {
+    fn run(
+        ip: String,
+        options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        let (phone_port, format) = (options.ifm_phone_port, options.ifm_format);
+        match options.ifm_transport {
+            IFacialMocapTransport::Tcp => Self::run_tcp(ip, phone_port, format, sender, active),
+            IFacialMocapTransport::Udp => Self::run_udp(ip, phone_port, format, sender, active),
+        }
+    }
+}
+
+static FRAME_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("___iFacialMocaptrackingStatus-[01]\\|").unwrap());
+
+// Takes every complete frame out of the buffer, frames start with the tracking status
+fn split_text_frames(partial_buffer: &mut String) -> Vec<String> {
+    let mut frames = Vec::new();
+
+    let mut matches: Vec<_> = FRAME_START.find_iter(partial_buffer).collect();
+    while matches.len() >= 2 {
+        let first_start = matches[0].start();
+        let second_start = matches[1].start();
+
+        frames.push(partial_buffer[first_start..second_start].to_string());
+
+        partial_buffer.replace_range(0..second_start, "");
+        matches = FRAME_START.find_iter(partial_buffer).collect();
     }
+    frames
+}
+
+// Takes every complete JSON object out of the buffer
+fn split_json_frames(partial_buffer: &mut String) -> Vec<String> {
+    let mut frames = Vec::new();
+    let mut consumed = 0;
+
+    loop {
+        let rest = &partial_buffer[consumed..];
+        let mut stream =
+            serde_json::Deserializer::from_str(rest).into_iter::<serde::de::IgnoredAny>();
+        match stream.next() {
+            Some(Ok(_)) => {
+                frames.push(rest[..stream.byte_offset()].to_string());
+                consumed += stream.byte_offset();
+            }
+            // Rest of the frame has not arrived yet
+            Some(Err(error)) if error.is_eof() => break,
+            // Otherwise it would stay in the buffer forever, the next frame starts with {
+            Some(Err(error)) => {
+                warn!("Skipping invalid JSON frame: {}", error);
+                consumed += rest
+                    .char_indices()
+                    .skip(1)
+                    .find_map(|(index, c)| (c == '{').then_some(index))
+                    .unwrap_or(rest.len());
+            }
+            None => break,
+        }
+    }
+
+    partial_buffer.replace_range(0..consumed, "");
+    frames
 }
 
 fn get_coordinate_values(part: &str) -> Result<(String, Vec<f64>), Box<dyn std::error::Error>> {
@@ -110,41 +266,27 @@ fn get_coordinate_values(part: &str) -> Result<(String, Vec<f64>), Box<dyn std::
     Ok((name, values))
 }
 
-fn capitalize_first_letter(string: &str) -> String {
-    let mut characters = string.chars();
-    match characters.next() {
-        None => String::new(),
-        Some(first) => first.to_uppercase().collect::<String>() + characters.as_str(),
-    }
-}
-
 fn parse_tracking_string(string: &str) -> Result<TrackingResponse, Box<dyn std::error::Error>> {
-    let parts: Vec<&str> = string.split('=').collect();
-    if parts.len() != 2 {
-        return Err("Invalid input string format".into());
-    }
+    parse_delimited_tracking_string(string, '-')
+}
 
-    let status_part = parts[0];
-    let coords_part = parts[1];
+// Shared with apps using the same layout, which differ only by the separator of blendshape values
+pub(crate) fn parse_delimited_tracking_string(
+    string: &str,
+    value_separator: char,
+) -> Result<TrackingResponse, Box<dyn std::error::Error>> {
+    let (status_part, coords_part) = string
+        .split_once('=')
+        .ok_or("Invalid input string format")?;
 
-    let mut status_map: HashMap<String, i16> = HashMap::new();
+    let mut status_map: HashMap<String, f64> = HashMap::new();
     for item in status_part.trim().split('|') {
         if !item.is_empty() {
-            let kv: Vec<&str> = item.split('-').collect();
+            let kv: Vec<&str> = item.split(value_separator).collect();
             if kv.len() == 2 {
-                let mut key = capitalize_first_letter(&kv[0].to_string());
-                if !key.eq("___iFacialMocaptrackingStatus-0") && key.contains("_") {
-                    let end_index = key.len();
-                    let start_index = end_index - 2;
-
-                    if key.ends_with("_L") {
-                        key.replace_range(start_index..end_index, "Left");
-                    } else if key.ends_with("_R") {
-                        key.replace_range(start_index..end_index, "Right");
-                    }
-                }
+                let key = normalize_shape_name(kv[0]);
 
-                let value: i16 = kv[1].parse()?;
+                let value: f64 = kv[1].parse()?;
                 status_map.insert(key, value);
             }
         }
@@ -153,10 +295,70 @@ fn parse_tracking_string(string: &str) -> Result<TrackingResponse, Box<dyn std::
     let coords_parts: Vec<&str> = coords_part.trim().split('|').collect();
 
     let (_, head_values) = get_coordinate_values(
-        *(coords_parts
-            .get(0)
-            .ok_or("Missing coordinates for \"head\"")?),
+        coords_parts
+            .first()
+            .ok_or("Missing coordinates for \"head\"")?,
     )?;
+    // Useless thing for now
+    let (_, right_eye_values) = get_coordinate_values(
+        coords_parts
+            .get(1)
+            .ok_or("Missing coordinates for \"rightEye\"")?,
+    )?;
+    let (_, left_eye_values) = get_coordinate_values(
+        coords_parts
+            .get(2)
+            .ok_or("Missing coordinates for \"leftEye\"")?,
+    )?;
+
+    build_tracking_response(
+        status_map,
+        &head_values,
+        &right_eye_values,
+        &left_eye_values,
+    )
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TrackingJson {
+    #[serde(alias = "blendshapes")]
+    blend_shapes: HashMap<String, f64>,
+    head: Vec<f64>,
+    right_eye: Vec<f64>,
+    left_eye: Vec<f64>,
+    tracking_status: Option<f64>,
+}
+
+fn parse_tracking_json(string: &str) -> Result<TrackingResponse, Box<dyn std::error::Error>> {
+    let data: TrackingJson = serde_json::from_str(string)?;
+
+    let mut status_map: HashMap<String, f64> = data
+        .blend_shapes
+        .iter()
+        .map(|(k, v)| (normalize_shape_name(k), *v))
+        .collect();
+    if let Some(status) = data.tracking_status {
+        status_map.insert("___iFacialMocaptrackingStatus".into(), status);
+    }
+
+    build_tracking_response(status_map, &data.head, &data.right_eye, &data.left_eye)
+}
+
+// Values of blendshapes are 0...100, head is "rotX,rotY,rotZ,posX,posY,posZ", eyes are "rotX,rotY,rotZ"
+fn build_tracking_response(
+    status_map: HashMap<String, f64>,
+    head_values: &[f64],
+    right_eye_values: &[f64],
+    left_eye_values: &[f64],
+) -> Result<TrackingResponse, Box<dyn std::error::Error>> {
+    if head_values.len() < 6 {
+        return Err("Not enough values for \"head\"".into());
+    }
+    if right_eye_values.len() < 3 || left_eye_values.len() < 3 {
+        return Err("Not enough values for eyes".into());
+    }
+
     let head_rotation = Cords {
         // I really don't understand why that thing reverted
         x: head_values[1],
@@ -168,32 +370,23 @@ fn parse_tracking_string(string: &str) -> Result<TrackingResponse, Box<dyn std::
         y: head_values[4],
         z: head_values[5],
     };
-
-    // Useless thing for now
-    let (_, right_eye_values) = get_coordinate_values(
-        *(coords_parts
-            .get(1)
-            .ok_or("Missing coordinates for \"rightEye\"")?),
-    )?;
-    let (_, left_eye_values) = get_coordinate_values(
-        *(coords_parts
-            .get(2)
-            .ok_or("Missing coordinates for \"leftEye\"")?),
-    )?;
     let left_eye = Cords {
         x: left_eye_values[0],
         y: left_eye_values[1],
         z: left_eye_values[2],
     };
 
-    let face_found = status_map["___iFacialMocaptrackingStatus"] == 1;
+    // Missing status means the phone doesn't report it, so the face is considered found
+    let face_found = status_map
+        .get("___iFacialMocaptrackingStatus")
+        .is_none_or(|status| *status == 1.0);
 
     let mut blend_shapes: Vec<Shape> = status_map
         .iter()
         .filter(|(k, _)| !k.starts_with("___"))
         .map(|(k, v)| Shape {
             k: k.clone(),
-            v: (*v as f64) / 100.0, // magic value, nevermind
+            v: v / 100.0, // magic value, nevermind
         })
         .collect();
     blend_shapes.push(Shape {
@@ -218,7 +411,7 @@ fn parse_tracking_string(string: &str) -> Result<TrackingResponse, Box<dyn std::
     });
     blend_shapes.push(Shape {
         k: "LeftEyeZ".into(),
-        v: left_eye_values[0],
+        v: left_eye_values[2],
     });
 
     let timestamp = get_current_timestamp();
@@ -233,3 +426,109 @@ fn parse_tracking_string(string: &str) -> Result<TrackingResponse, Box<dyn std::
         blend_shapes,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(response: &TrackingResponse, name: &str) -> f64 {
+        response
+            .blend_shapes
+            .iter()
+            .find(|shape| shape.k == name)
+            .unwrap()
+            .v
+    }
+
+    const TEXT_FRAME: &str = "mouthSmile_R-0|eyeBlink_L-100|jawOpen-35|=head#-10.5,2.5,3,0.1,-0.2,0.3|rightEye#1,2,3|leftEye#4,5,6|";
+    const JSON_FRAME: &str = r#"{"blendShapes": {"mouthSmile_R": 0, "eyeBlink_L": 100, "jawOpen": 35},
+        "head": [-10.5, 2.5, 3, 0.1, -0.2, 0.3], "rightEye": [1, 2, 3], "leftEye": [4, 5, 6]}"#;
+
+    #[test]
+    fn parses_text_and_json_frames_the_same_way() {
+        for response in [
+            parse_tracking_string(TEXT_FRAME).unwrap(),
+            parse_tracking_json(JSON_FRAME).unwrap(),
+        ] {
+            assert!(response.face_found);
+            // Rotation X and Y are swapped by the app
+            assert_eq!(
+                (
+                    response.rotation.x,
+                    response.rotation.y,
+                    response.rotation.z
+                ),
+                (2.5, -10.5, 3.0)
+            );
+            assert_eq!(
+                (
+                    response.position.x,
+                    response.position.y,
+                    response.position.z
+                ),
+                (0.1, -0.2, 0.3)
+            );
+            assert_eq!(shape(&response, "EyeBlinkLeft"), 1.0);
+            assert_eq!(shape(&response, "JawOpen"), 0.35);
+            assert_eq!(shape(&response, "MouthSmileRight"), 0.0);
+            assert_eq!(shape(&response, "RightEyeZ"), 3.0);
+            assert_eq!(
+                [
+                    shape(&response, "LeftEyeX"),
+                    shape(&response, "LeftEyeY"),
+                    shape(&response, "LeftEyeZ")
+                ],
+                [4.0, 5.0, 6.0]
+            );
+        }
+    }
+
+    #[test]
+    fn reads_tracking_status() {
+        let lost = parse_tracking_json(
+            r#"{"blendshapes": {}, "head": [0, 0, 0, 0, 0, 0], "rightEye": [0, 0, 0], "leftEye": [0, 0, 0], "trackingStatus": 0}"#,
+        )
+        .unwrap();
+        assert!(!lost.face_found);
+        let lost = parse_tracking_string(
+            "___iFacialMocaptrackingStatus-0|=head#0,0,0,0,0,0|rightEye#0,0,0|leftEye#0,0,0|",
+        )
+        .unwrap();
+        assert!(!lost.face_found);
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert!(parse_tracking_string("jawOpen-35|").is_err());
+        assert!(
+            parse_tracking_string("jawOpen-x|=head#0,0,0,0,0,0|rightEye#0,0,0|leftEye#0,0,0|")
+                .is_err()
+        );
+        assert!(parse_tracking_string("=head#0,0,0|rightEye#0,0,0|leftEye#0,0,0|").is_err());
+        assert!(parse_tracking_string("=head#0,0,0,0,0,0|rightEye#0,0,0|").is_err());
+        assert!(parse_tracking_json(r#"{"blendShapes": {}, "head": [0, 0, 0, 0, 0, 0]}"#).is_err());
+        assert!(parse_tracking_json(
+            r#"{"blendShapes": {}, "head": [0, 0, 0, 0, 0, 0], "rightEye": [0], "leftEye": [0, 0, 0]}"#
+        )
+        .is_err());
+        assert!(parse_tracking_json(&JSON_FRAME[..40]).is_err());
+    }
+
+    #[test]
+    fn splits_json_frames_and_skips_invalid_ones() {
+        let mut buffer = r#"{"a": 1}{"b": x}{"c": 3}{"d":"#.to_string();
+        assert_eq!(
+            split_json_frames(&mut buffer),
+            [r#"{"a": 1}"#, r#"{"c": 3}"#]
+        );
+        assert_eq!(buffer, r#"{"d":"#);
+
+        buffer.push_str(" 4}");
+        assert_eq!(split_json_frames(&mut buffer), [r#"{"d": 4}"#]);
+        assert_eq!(buffer, "");
+
+        buffer.push_str("garbage");
+        assert!(split_json_frames(&mut buffer).is_empty());
+        assert_eq!(buffer, "");
+    }
+}
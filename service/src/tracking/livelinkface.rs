@@ -0,0 +1,324 @@
+use std::{
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    time,
+};
+
+use log::{error, info, warn};
+
+use crate::{
+    tracking::{
+        client::{TrackingClient, TrackingOptions},
+        response::{Cords, Shape, TrackingResponse},
+    },
+    utils::get_current_timestamp,
+};
+
+// Order of curves in the Live Link packet
+const CURVES: [&str; 61] = [
+    "EyeBlinkLeft",
+    "EyeLookDownLeft",
+    "EyeLookInLeft",
+    "EyeLookOutLeft",
+    "EyeLookUpLeft",
+    "EyeSquintLeft",
+    "EyeWideLeft",
+    "EyeBlinkRight",
+    "EyeLookDownRight",
+    "EyeLookInRight",
+    "EyeLookOutRight",
+    "EyeLookUpRight",
+    "EyeSquintRight",
+    "EyeWideRight",
+    "JawForward",
+    "JawRight",
+    "JawLeft",
+    "JawOpen",
+    "MouthClose",
+    "MouthFunnel",
+    "MouthPucker",
+    "MouthRight",
+    "MouthLeft",
+    "MouthSmileLeft",
+    "MouthSmileRight",
+    "MouthFrownLeft",
+    "MouthFrownRight",
+    "MouthDimpleLeft",
+    "MouthDimpleRight",
+    "MouthStretchLeft",
+    "MouthStretchRight",
+    "MouthRollLower",
+    "MouthRollUpper",
+    "MouthShrugLower",
+    "MouthShrugUpper",
+    "MouthPressLeft",
+    "MouthPressRight",
+    "MouthLowerDownLeft",
+    "MouthLowerDownRight",
+    "MouthUpperUpLeft",
+    "MouthUpperUpRight",
+    "BrowDownLeft",
+    "BrowDownRight",
+    "BrowInnerUp",
+    "BrowOuterUpLeft",
+    "BrowOuterUpRight",
+    "CheekPuff",
+    "CheekSquintLeft",
+    "CheekSquintRight",
+    "NoseSneerLeft",
+    "NoseSneerRight",
+    "TongueOut",
+    "HeadYaw",
+    "HeadPitch",
+    "HeadRoll",
+    "LeftEyeYaw",
+    "LeftEyePitch",
+    "LeftEyeRoll",
+    "RightEyeYaw",
+    "RightEyePitch",
+    "RightEyeRoll",
+];
+// Everything before that index is an ARKit blendshape
+const HEAD_YAW: usize = 52;
+
+#[derive(Debug)]
+pub struct LiveLinkPacket {
+    pub version: u8,
+    pub device_id: String,
+    pub subject_name: String,
+    pub frame_number: i32,
+    pub sub_frame: f32,
+    pub frame_rate_numerator: i32,
+    pub frame_rate_denominator: i32,
+    pub curves: Vec<f32>,
+}
+
+struct PacketReader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl PacketReader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn std::error::Error>> {
+        let bytes = self
+            .buf
+            .get(self.offset..self.offset + N)
+            .ok_or("Unexpected end of packet")?;
+        self.offset += N;
+        Ok(bytes.try_into()?)
+    }
+
+    fn u8(&mut self) -> Result<u8, Box<dyn std::error::Error>> {
+        Ok(u8::from_be_bytes(self.bytes()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, Box<dyn std::error::Error>> {
+        Ok(i32::from_be_bytes(self.bytes()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, Box<dyn std::error::Error>> {
+        Ok(f32::from_be_bytes(self.bytes()?))
+    }
+
+    // u32 length followed by UTF-8
+    fn string(&mut self) -> Result<String, Box<dyn std::error::Error>> {
+        let length = u32::from_be_bytes(self.bytes()?) as usize;
+        let bytes = self
+            .buf
+            .get(self.offset..self.offset + length)
+            .ok_or("Unexpected end of packet")?;
+        self.offset += length;
+        Ok(String::from_utf8(bytes.to_vec())?)
+    }
+}
+
+impl LiveLinkPacket {
+    pub fn parse(buf: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader = PacketReader { buf, offset: 0 };
+
+        let version = reader.u8()?;
+        let device_id = reader.string()?;
+        let subject_name = reader.string()?;
+        let frame_number = reader.i32()?;
+        let sub_frame = reader.f32()?;
+        let frame_rate_numerator = reader.i32()?;
+        let frame_rate_denominator = reader.i32()?;
+        let count = reader.u8()? as usize;
+        if count != CURVES.len() {
+            return Err(format!("Unexpected count of curves: {}", count).into());
+        }
+        let curves = (0..count)
+            .map(|_| reader.f32())
+            .collect::<Result<Vec<f32>, _>>()?;
+
+        Ok(Self {
+            version,
+            device_id,
+            subject_name,
+            frame_number,
+            sub_frame,
+            frame_rate_numerator,
+            frame_rate_denominator,
+            curves,
+        })
+    }
+
+    // Head and eye curves are in radians
+    fn angles(&self, start: usize) -> Cords {
+        let yaw = self.curves[start] as f64;
+        let pitch = self.curves[start + 1] as f64;
+        let roll = self.curves[start + 2] as f64;
+        Cords {
+            x: pitch.to_degrees(),
+            y: yaw.to_degrees(),
+            z: roll.to_degrees(),
+        }
+    }
+
+    pub fn to_response(&self) -> TrackingResponse {
+        let mut blend_shapes: Vec<Shape> = CURVES[..HEAD_YAW]
+            .iter()
+            .zip(&self.curves)
+            .map(|(name, value)| Shape {
+                k: name.to_string(),
+                v: *value as f64,
+            })
+            .collect();
+
+        let left_eye = self.angles(HEAD_YAW + 3);
+        let right_eye = self.angles(HEAD_YAW + 6);
+        for (prefix, eye) in [("LeftEye", &left_eye), ("RightEye", &right_eye)] {
+            for (axis, value) in [("X", eye.x), ("Y", eye.y), ("Z", eye.z)] {
+                blend_shapes.push(Shape {
+                    k: format!("{prefix}{axis}"),
+                    v: value,
+                });
+            }
+        }
+
+        TrackingResponse {
+            timestamp: get_current_timestamp(),
+            hotkey: 0,
+            // There is no tracking status in the packet
+            face_found: true,
+            rotation: self.angles(HEAD_YAW),
+            position: Cords::default(),
+            eye_left: left_eye,
+            blend_shapes,
+        }
+    }
+}
+
+pub struct LiveLinkFaceTrackingClient;
+
+impl LiveLinkFaceTrackingClient {
+    pub const PORT: u16 = 11111;
+
+    pub fn listen(address: &str, sender: Sender<TrackingResponse>, active: Arc<AtomicBool>) {
+        let socket = match UdpSocket::bind(address) {
+            Ok(socket) => socket,
+            Err(error) => {
+                error!("Unable to listen on {}: {}", address, error);
+                return;
+            }
+        };
+        let _ = socket.set_read_timeout(Some(time::Duration::new(2, 0)));
+        info!("Live Link receiver listening on {}", address);
+
+        let mut buf = [0; 4096];
+
+        while active.load(Ordering::Relaxed) {
+            match socket.recv_from(&mut buf) {
+                Ok((amt, _src)) => match LiveLinkPacket::parse(&buf[..amt]) {
+                    Ok(packet) => Self::send(&sender, packet.to_response()),
+                    Err(error) => warn!("Unable to parse Live Link packet: {}", error),
+                },
+                Err(error) => {
+                    warn!("Unnable to receive: {}", error)
+                }
+            }
+        }
+    }
+}
+
+impl TrackingClient for LiveLinkFaceTrackingClient {
+    // Target IP and port are set in the app, so the phone IP is not needed
+    fn run(
+        _ip: String,
+        _options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        Self::listen(&format!("0.0.0.0:{}", Self::PORT), sender, active);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evalexpr::{Context, HashMapContext};
+
+    use super::*;
+    use crate::transform::insert_tracking_info;
+
+    fn string(value: &str) -> Vec<u8> {
+        let mut bytes = (value.len() as u32).to_be_bytes().to_vec();
+        bytes.extend(value.as_bytes());
+        bytes
+    }
+
+    fn packet(curves: &[f32]) -> Vec<u8> {
+        let mut packet = vec![6];
+        packet.extend(string("device-id"));
+        packet.extend(string("iPhone"));
+        packet.extend(1234i32.to_be_bytes());
+        packet.extend(0.5f32.to_be_bytes());
+        packet.extend(60i32.to_be_bytes());
+        packet.extend(1i32.to_be_bytes());
+        packet.push(curves.len() as u8);
+        for curve in curves {
+            packet.extend(curve.to_be_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn maps_curves_to_blend_shapes_and_head_rotation() {
+        let mut curves: Vec<f32> = (0..CURVES.len()).map(|i| i as f32 / 100.0).collect();
+        curves[HEAD_YAW..HEAD_YAW + 3].copy_from_slice(&[0.5, 0.25, -0.1]);
+
+        let packet = LiveLinkPacket::parse(&packet(&curves)).unwrap();
+        assert_eq!(packet.device_id, "device-id");
+        assert_eq!(packet.subject_name, "iPhone");
+        assert_eq!(packet.frame_number, 1234);
+        assert_eq!(packet.frame_rate_numerator, 60);
+        assert_eq!(packet.curves, curves);
+
+        let response = packet.to_response();
+        assert_eq!(response.blend_shapes.len(), HEAD_YAW + 6);
+        for (index, name) in CURVES[..HEAD_YAW].iter().enumerate() {
+            let shape = &response.blend_shapes[index];
+            assert_eq!(shape.k, *name);
+            assert_eq!(shape.v, curves[index] as f64);
+        }
+
+        let mut context = HashMapContext::new();
+        insert_tracking_info(&mut context, None, &response).unwrap();
+        let value = |name: &str| context.get_value(name).unwrap().as_float().unwrap();
+        assert!((value("HeadRotX") - 0.25f32.to_degrees() as f64).abs() < 1e-4);
+        assert!((value("HeadRotY") - 0.5f32.to_degrees() as f64).abs() < 1e-4);
+        assert!((value("HeadRotZ") + 0.1f32.to_degrees() as f64).abs() < 1e-4);
+        assert_eq!(value("JawOpen"), curves[17] as f64);
+        assert!(context.get_value("HeadYaw").is_none());
+    }
+
+    #[test]
+    fn rejects_other_counts_of_curves() {
+        assert!(LiveLinkPacket::parse(&packet(&[0.0; 52])).is_err());
+        let full = packet(&[0.0; 61]);
+        assert!(LiveLinkPacket::parse(&full[..full.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::{atomic::AtomicBool, mpsc::Sender, Arc};
+
+use serde_json::{Map, Value};
+
+use crate::{
+    tracking::{
+        arkit::canonical_shape_name,
+        client::{TrackingClient, TrackingOptions},
+        response::{Cords, Shape, TrackingResponse},
+        vtubestudio::VTubeStudioTrackingClient,
+    },
+    utils::get_current_timestamp,
+};
+
+// MeowFace emulates the VTubeStudio iPhone protocol, but its JSON is not always the same:
+// key casing differs and some fields are missing, so everything is looked up leniently
+pub struct MeowFaceTrackingClient;
+
+fn get_field<'a>(object: &'a Map<String, Value>, name: &str) -> Option<&'a Value> {
+    object
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+fn parse_cords(value: Option<&Value>) -> Cords {
+    let Some(Value::Object(object)) = value else {
+        return Cords::default();
+    };
+    let axis = |name| {
+        get_field(object, name)
+            .and_then(Value::as_f64)
+            .unwrap_or(0.0)
+    };
+    Cords {
+        x: axis("x"),
+        y: axis("y"),
+        z: axis("z"),
+    }
+}
+
+// Accepts both [{"k": "eyeBlink_L", "v": 0.1}, ...] and {"eyeBlink_L": 0.1, ...}
+fn parse_blend_shapes(value: Option<&Value>) -> Vec<Shape> {
+    match value {
+        Some(Value::Array(shapes)) => shapes
+            .iter()
+            .filter_map(|shape| {
+                let shape = shape.as_object()?;
+                let name = get_field(shape, "k")?.as_str()?;
+                let value = get_field(shape, "v")?.as_f64()?;
+                Some(Shape {
+                    k: canonical_shape_name(name),
+                    v: value,
+                })
+            })
+            .collect(),
+        Some(Value::Object(shapes)) => shapes
+            .iter()
+            .filter_map(|(name, value)| {
+                Some(Shape {
+                    k: canonical_shape_name(name),
+                    v: value.as_f64()?,
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+pub fn parse_tolerant(buf: &[u8]) -> Result<TrackingResponse, Box<dyn std::error::Error>> {
+    let value: Value = serde_json::from_slice(buf)?;
+    let object = value.as_object().ok_or("Tracking data is not an object")?;
+
+    Ok(TrackingResponse {
+        timestamp: get_field(object, "Timestamp")
+            .and_then(Value::as_u64)
+            .unwrap_or_else(get_current_timestamp),
+        hotkey: get_field(object, "Hotkey")
+            .and_then(Value::as_i64)
+            .unwrap_or(0) as i16,
+        face_found: get_field(object, "FaceFound")
+            .and_then(Value::as_bool)
+            .unwrap_or(true),
+        rotation: parse_cords(get_field(object, "Rotation")),
+        position: parse_cords(get_field(object, "Position")),
+        eye_left: parse_cords(get_field(object, "EyeLeft")),
+        blend_shapes: parse_blend_shapes(get_field(object, "BlendShapes")),
+    })
+}
+
+impl TrackingClient for MeowFaceTrackingClient {
+    fn run(
+        ip: String,
+        options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        VTubeStudioTrackingClient::request_tracking(ip, options, parse_tolerant, sender, active);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shapes(response: &TrackingResponse) -> Vec<(&str, f64)> {
+        let mut shapes: Vec<_> = response
+            .blend_shapes
+            .iter()
+            .map(|shape| (shape.k.as_str(), shape.v))
+            .collect();
+        shapes.sort_by(|a, b| a.0.cmp(b.0));
+        shapes
+    }
+
+    #[test]
+    fn parses_vtubestudio_layout() {
+        let response = parse_tolerant(
+            br#"{"Timestamp": 1700000000, "Hotkey": 3, "FaceFound": false,
+                "Rotation": {"x": 1.5, "y": -2, "z": 3}, "Position": {"x": 4, "y": 5, "z": 6},
+                "EyeLeft": {"x": 7, "y": 8, "z": 9},
+                "BlendShapes": [{"k": "EyeBlinkLeft", "v": 0.5}, {"k": "JawOpen", "v": 0.25}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.timestamp, 1700000000);
+        assert_eq!(response.hotkey, 3);
+        assert!(!response.face_found);
+        assert_eq!(
+            (
+                response.rotation.x,
+                response.rotation.y,
+                response.rotation.z
+            ),
+            (1.5, -2.0, 3.0)
+        );
+        assert_eq!(response.position.z, 6.0);
+        assert_eq!(response.eye_left.y, 8.0);
+        assert_eq!(
+            shapes(&response),
+            [("EyeBlinkLeft", 0.5), ("JawOpen", 0.25)]
+        );
+    }
+
+    #[test]
+    fn accepts_other_casing_and_missing_fields() {
+        let response = parse_tolerant(
+            br#"{"rotation": {"X": 10}, "blendshapes": {"eyeblink_l": 0.5, "JAW_OPEN": 1, "mouthSmile_R": 0.2, "bad": "x"}}"#,
+        )
+        .unwrap();
+        // Missing FaceFound means tracking
+        assert!(response.face_found);
+        assert_eq!(response.hotkey, 0);
+        assert_eq!(
+            (
+                response.rotation.x,
+                response.rotation.y,
+                response.rotation.z
+            ),
+            (10.0, 0.0, 0.0)
+        );
+        assert_eq!(response.position.x, 0.0);
+        assert_eq!(
+            shapes(&response),
+            [
+                ("EyeBlinkLeft", 0.5),
+                ("JawOpen", 1.0),
+                ("MouthSmileRight", 0.2)
+            ]
+        );
+
+        // Shapes without a name or a number are skipped
+        let response = parse_tolerant(
+            br#"{"BlendShapes": [{"k": "JawOpen"}, {"v": 1}, 5, {"K": "jawOpen", "V": 0.5}]}"#,
+        )
+        .unwrap();
+        assert_eq!(shapes(&response), [("JawOpen", 0.5)]);
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert!(parse_tolerant(br#"{"FaceFound": true, "BlendShapes": [{"k": "JawOpen""#).is_err());
+        assert!(parse_tolerant(b"").is_err());
+        assert!(parse_tolerant(b"[1, 2]").is_err());
+        assert!(parse_tolerant(b"{} trailing").is_err());
+    }
+}
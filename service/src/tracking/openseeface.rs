@@ -0,0 +1,336 @@
+use std::{
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    time,
+};
+
+use log::{error, info, warn};
+
+use crate::tracking::{
+    client::{TrackingClient, TrackingOptions},
+    response::{Cords, Shape, TrackingResponse},
+};
+
+pub const LANDMARKS: usize = 68;
+const POINTS_3D: usize = 70;
+pub const FEATURES: [&str; 14] = [
+    "EyeLeft",
+    "EyeRight",
+    "EyebrowSteepnessLeft",
+    "EyebrowUpDownLeft",
+    "EyebrowQuirkLeft",
+    "EyebrowSteepnessRight",
+    "EyebrowUpDownRight",
+    "EyebrowQuirkRight",
+    "MouthCornerUpDownLeft",
+    "MouthCornerInOutLeft",
+    "MouthCornerUpDownRight",
+    "MouthCornerInOutRight",
+    "MouthOpen",
+    "MouthWide",
+];
+
+#[derive(Debug)]
+pub struct OpenSeeFacePacket {
+    pub timestamp: f64,
+    pub face_id: i32,
+    pub width: f32,
+    pub height: f32,
+    pub eye_open_right: f32,
+    pub eye_open_left: f32,
+    pub success: bool,
+    pub pnp_error: f32,
+    pub quaternion: [f32; 4],
+    pub euler: [f32; 3],
+    pub translation: [f32; 3],
+    pub confidence: Vec<f32>,
+    // (x, y) in image pixels
+    pub landmarks: Vec<(f32, f32)>,
+    pub features: [f32; 14],
+}
+
+impl OpenSeeFacePacket {
+    // Timestamp, face id, size, eye blinks, success, PnP error, quaternion, euler, translation
+    const HEADER_SIZE: usize = 8 + 4 + 8 + 8 + 1 + 4 + 16 + 12 + 12;
+    pub const SIZE: usize = Self::HEADER_SIZE
+        + LANDMARKS * 4
+        + LANDMARKS * 2 * 4
+        + POINTS_3D * 3 * 4
+        + FEATURES.len() * 4;
+
+    pub fn parse(buf: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        if buf.len() < Self::SIZE {
+            return Err(format!(
+                "OpenSeeFace packet is too short: {} < {}",
+                buf.len(),
+                Self::SIZE
+            )
+            .into());
+        }
+
+        let mut reader = PacketReader { buf, offset: 0 };
+
+        let timestamp = reader.f64();
+        let face_id = reader.i32();
+        let width = reader.f32();
+        let height = reader.f32();
+        let eye_open_right = reader.f32();
+        let eye_open_left = reader.f32();
+        let success = reader.u8() != 0;
+        let pnp_error = reader.f32();
+        let quaternion = reader.f32_array();
+        let euler = reader.f32_array();
+        let translation = reader.f32_array();
+        let confidence = (0..LANDMARKS).map(|_| reader.f32()).collect();
+        let landmarks = (0..LANDMARKS)
+            .map(|_| {
+                // Sent as (y, x)
+                let y = reader.f32();
+                let x = reader.f32();
+                (x, y)
+            })
+            .collect();
+        // 3D points are not used
+        reader.offset += POINTS_3D * 3 * 4;
+        let features = reader.f32_array();
+
+        Ok(Self {
+            timestamp,
+            face_id,
+            width,
+            height,
+            eye_open_right,
+            eye_open_left,
+            success,
+            pnp_error,
+            quaternion,
+            euler,
+            translation,
+            confidence,
+            landmarks,
+            features,
+        })
+    }
+
+    pub fn to_response(&self) -> TrackingResponse {
+        let mut blend_shapes = vec![
+            Shape {
+                k: "EyeBlinkLeft".into(),
+                v: (1.0 - self.eye_open_left as f64).clamp(0.0, 1.0),
+            },
+            Shape {
+                k: "EyeBlinkRight".into(),
+                v: (1.0 - self.eye_open_right as f64).clamp(0.0, 1.0),
+            },
+            Shape {
+                k: "JawOpen".into(),
+                v: (self.features[12] as f64).clamp(0.0, 1.0),
+            },
+        ];
+        for (name, value) in FEATURES.iter().zip(self.features) {
+            blend_shapes.push(Shape {
+                k: name.to_string(),
+                v: value as f64,
+            });
+        }
+        for (index, (x, y)) in self.landmarks.iter().enumerate() {
+            blend_shapes.push(Shape {
+                k: format!("Landmark{index}X"),
+                v: *x as f64,
+            });
+            blend_shapes.push(Shape {
+                k: format!("Landmark{index}Y"),
+                v: *y as f64,
+            });
+        }
+
+        TrackingResponse {
+            timestamp: self.timestamp as u64,
+            hotkey: 0,
+            face_found: self.success,
+            rotation: Cords {
+                // Pitch is around +-180 while looking at the camera
+                x: wrap_degrees(self.euler[0] as f64 + 180.0),
+                y: self.euler[1] as f64,
+                z: self.euler[2] as f64,
+            },
+            position: Cords {
+                x: self.translation[0] as f64,
+                y: self.translation[1] as f64,
+                z: self.translation[2] as f64,
+            },
+            eye_left: Cords::default(),
+            blend_shapes,
+        }
+    }
+}
+
+fn wrap_degrees(angle: f64) -> f64 {
+    (angle + 180.0).rem_euclid(360.0) - 180.0
+}
+
+// Size is checked before reading, so there is no bounds handling here
+struct PacketReader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl PacketReader<'_> {
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let bytes = self.buf[self.offset..self.offset + N].try_into().unwrap();
+        self.offset += N;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        u8::from_le_bytes(self.bytes())
+    }
+
+    fn i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.bytes())
+    }
+
+    fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.bytes())
+    }
+
+    fn f64(&mut self) -> f64 {
+        f64::from_le_bytes(self.bytes())
+    }
+
+    fn f32_array<const N: usize>(&mut self) -> [f32; N] {
+        std::array::from_fn(|_| self.f32())
+    }
+}
+
+pub struct OpenSeeFaceTrackingClient;
+
+impl OpenSeeFaceTrackingClient {
+    pub const PORT: u16 = 11573;
+
+    pub fn listen(address: &str, sender: Sender<TrackingResponse>, active: Arc<AtomicBool>) {
+        let socket = match UdpSocket::bind(address) {
+            Ok(socket) => socket,
+            Err(error) => {
+                error!("Unable to listen on {}: {}", address, error);
+                return;
+            }
+        };
+        let _ = socket.set_read_timeout(Some(time::Duration::new(2, 0)));
+        info!("OpenSeeFace receiver listening on {}", address);
+
+        let mut buf = [0; 65535];
+
+        while active.load(Ordering::Relaxed) {
+            match socket.recv_from(&mut buf) {
+                // One datagram holds a packet per tracked face, the first one is used
+                Ok((amt, _src)) => match OpenSeeFacePacket::parse(&buf[..amt]) {
+                    Ok(packet) => Self::send(&sender, packet.to_response()),
+                    Err(error) => warn!("Unable to parse OpenSeeFace packet: {}", error),
+                },
+                Err(error) => {
+                    warn!("Unnable to receive: {}", error)
+                }
+            }
+        }
+    }
+}
+
+impl TrackingClient for OpenSeeFaceTrackingClient {
+    // OpenSeeFace runs on the same PC and pushes packets, so the phone IP is not needed
+    fn run(
+        _ip: String,
+        _options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        Self::listen(&format!("0.0.0.0:{}", Self::PORT), sender, active);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(response: &TrackingResponse, name: &str) -> f64 {
+        response
+            .blend_shapes
+            .iter()
+            .find(|shape| shape.k == name)
+            .unwrap()
+            .v
+    }
+
+    fn packet(face_id: i32) -> Vec<u8> {
+        let mut packet = Vec::new();
+        let floats = |packet: &mut Vec<u8>, values: &[f32]| {
+            for value in values {
+                packet.extend(value.to_le_bytes());
+            }
+        };
+        packet.extend(12.5f64.to_le_bytes());
+        packet.extend(face_id.to_le_bytes());
+        floats(&mut packet, &[640.0, 480.0, 0.75, 0.25]);
+        packet.push(1);
+        floats(&mut packet, &[0.01, 0.0, 0.0, 0.0, 1.0]);
+        floats(&mut packet, &[-175.0, 10.0, 5.0, 1.0, 2.0, 3.0]);
+        floats(&mut packet, &[1.0; LANDMARKS]);
+        for index in 0..LANDMARKS {
+            // (y, x)
+            floats(&mut packet, &[index as f32, index as f32 * 2.0]);
+        }
+        floats(&mut packet, &[0.0; POINTS_3D * 3]);
+        let features: Vec<f32> = (0..FEATURES.len()).map(|i| i as f32 / 10.0).collect();
+        floats(&mut packet, &features);
+        packet
+    }
+
+    #[test]
+    fn parses_packet_of_one_face() {
+        let bytes = packet(0);
+        assert_eq!(bytes.len(), 1785);
+        assert_eq!(OpenSeeFacePacket::SIZE, 1785);
+
+        let packet = OpenSeeFacePacket::parse(&bytes).unwrap();
+        assert_eq!(packet.timestamp, 12.5);
+        assert_eq!((packet.width, packet.height), (640.0, 480.0));
+        assert!(packet.success);
+        assert_eq!(packet.landmarks[3], (6.0, 3.0));
+        assert_eq!(packet.features[13], 1.3);
+
+        let response = packet.to_response();
+        assert!(response.face_found);
+        // Pitch is turned around to be 0 while looking at the camera
+        assert!((response.rotation.x - 5.0).abs() < 1e-9);
+        assert_eq!((response.rotation.y, response.rotation.z), (10.0, 5.0));
+        assert_eq!(
+            (
+                response.position.x,
+                response.position.y,
+                response.position.z
+            ),
+            (1.0, 2.0, 3.0)
+        );
+        assert!((shape(&response, "EyeBlinkLeft") - 0.75).abs() < 1e-6);
+        assert!((shape(&response, "EyeBlinkRight") - 0.25).abs() < 1e-6);
+        // MouthOpen is 1.2, clamped as a blendshape
+        assert_eq!(shape(&response, "JawOpen"), 1.0);
+        assert!((shape(&response, "MouthOpen") - 1.2).abs() < 1e-6);
+        assert_eq!(shape(&response, "Landmark67X"), 134.0);
+        assert_eq!(shape(&response, "Landmark67Y"), 67.0);
+    }
+
+    #[test]
+    fn uses_first_face_and_rejects_short_packets() {
+        let mut bytes = packet(0);
+        bytes.extend(packet(1));
+        assert_eq!(OpenSeeFacePacket::parse(&bytes).unwrap().face_id, 0);
+
+        assert!(OpenSeeFacePacket::parse(&bytes[..OpenSeeFacePacket::SIZE - 1]).is_err());
+        assert!(OpenSeeFacePacket::parse(&[]).is_err());
+    }
+}
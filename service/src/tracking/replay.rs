@@ -0,0 +1,225 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+
+use crate::{
+    recording::RecordReader,
+    tracking::{
+        client::{TrackingClient, TrackingOptions},
+        response::TrackingResponse,
+    },
+};
+
+pub struct ReplayTrackingClient;
+
+impl ReplayTrackingClient {
+    // Long pauses in the recording are slept in parts to notice shutdown
+    const SLEEP_STEP: Duration = Duration::from_millis(100);
+
+    fn sleep_until(time: Instant, active: &AtomicBool) {
+        while active.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            if now >= time {
+                break;
+            }
+            thread::sleep((time - now).min(Self::SLEEP_STEP));
+        }
+    }
+
+    // Plays the recording once, false when there was nothing to play
+    fn replay(
+        options: &TrackingOptions,
+        sender: &Sender<TrackingResponse>,
+        active: &AtomicBool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut reader = RecordReader::open(&options.replay_file)?;
+        let speed = if options.replay_speed > 0.0 {
+            options.replay_speed
+        } else {
+            1.0
+        };
+
+        // Without a chosen source the first one in the recording is played
+        let mut source = options.replay_source.clone();
+        let mut first_received_ms = None;
+        let mut start = Instant::now();
+        let mut played = false;
+
+        while active.load(Ordering::Relaxed) {
+            let Some(record) = reader.read()? else {
+                break;
+            };
+            if source.is_empty() {
+                source = record.source.clone();
+                info!("Replaying tracking source {}", source);
+            }
+            if record.source != source {
+                continue;
+            }
+
+            let first_received_ms = *first_received_ms.get_or_insert(record.received_ms);
+            let elapsed_ms = record.received_ms.saturating_sub(first_received_ms);
+            if elapsed_ms < options.replay_start {
+                continue;
+            }
+            if !played {
+                start = Instant::now();
+                played = true;
+            }
+
+            let delay = (elapsed_ms - options.replay_start) as f64 / speed;
+            Self::sleep_until(start + Duration::from_secs_f64(delay / 1000.0), active);
+            Self::send(sender, record.response);
+        }
+
+        Ok(played)
+    }
+}
+
+impl TrackingClient for ReplayTrackingClient {
+    // Frames are read from a recorded file, so the phone IP is not needed
+    fn run(
+        _ip: String,
+        options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        info!("Replaying recording {}", options.replay_file);
+
+        while active.load(Ordering::Relaxed) {
+            match Self::replay(&options, &sender, &active) {
+                Ok(true) => {}
+                Ok(false) => {
+                    error!("Nothing to replay in {}", options.replay_file);
+                    break;
+                }
+                Err(error) => {
+                    error!("Unable to replay {}: {}", options.replay_file, error);
+                    break;
+                }
+            }
+            if !options.replay_loop {
+                info!("Replay finished");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, sync::mpsc};
+
+    use super::*;
+    use crate::{recording::Record, tracking::response::Cords};
+
+    fn write_recording(name: &str, records: &[(&str, u64)]) -> String {
+        let lines: Vec<String> = records
+            .iter()
+            .map(|(source, received_ms)| {
+                let record = Record {
+                    source: source.to_string(),
+                    received_ms: *received_ms,
+                    response: TrackingResponse {
+                        timestamp: *received_ms,
+                        hotkey: 0,
+                        face_found: true,
+                        rotation: Cords::default(),
+                        position: Cords::default(),
+                        eye_left: Cords::default(),
+                        blend_shapes: Vec::new(),
+                    },
+                };
+                serde_json::to_string(&record).unwrap()
+            })
+            .collect();
+        let path = env::temp_dir().join(name);
+        fs::write(&path, lines.join("\n")).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn plays_first_source_with_recorded_timing() {
+        let replay_file = write_recording(
+            "sandoitchi_replay_timing.jsonl",
+            &[("ifm", 1000), ("vmc2", 1050), ("ifm", 1100), ("ifm", 1300)],
+        );
+        let options = TrackingOptions {
+            replay_file,
+            // 100ms of the recording are skipped, the rest is played twice as fast
+            replay_speed: 2.0,
+            replay_start: 100,
+            ..TrackingOptions::default()
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        let played = ReplayTrackingClient::replay(&options, &sender, &AtomicBool::new(true));
+        let elapsed = start.elapsed();
+        assert!(played.unwrap());
+
+        let timestamps: Vec<u64> = receiver.try_iter().map(|frame| frame.timestamp).collect();
+        assert_eq!(timestamps, [1100, 1300]);
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(300), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn plays_chosen_source_only() {
+        let replay_file = write_recording(
+            "sandoitchi_replay_source.jsonl",
+            &[("ifm", 1000), ("vmc2", 1050), ("ifm", 1100), ("vmc2", 1070)],
+        );
+        let mut options = TrackingOptions {
+            replay_file,
+            replay_speed: 0.0,
+            replay_source: "vmc2".into(),
+            ..TrackingOptions::default()
+        };
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        assert!(ReplayTrackingClient::replay(&options, &sender, &AtomicBool::new(true)).unwrap());
+        // Speed 0 is played in real time
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        let timestamps: Vec<u64> = receiver.try_iter().map(|frame| frame.timestamp).collect();
+        assert_eq!(timestamps, [1050, 1070]);
+
+        // Nothing is left after the start
+        options.replay_start = 1000;
+        assert!(!ReplayTrackingClient::replay(&options, &sender, &AtomicBool::new(true)).unwrap());
+    }
+
+    #[test]
+    fn stops_sleeping_when_inactive() {
+        let replay_file = write_recording(
+            "sandoitchi_replay_inactive.jsonl",
+            &[("ifm", 0), ("ifm", 60_000)],
+        );
+        let options = TrackingOptions {
+            replay_file,
+            ..TrackingOptions::default()
+        };
+        let active = Arc::new(AtomicBool::new(true));
+        let stopper = {
+            let active = Arc::clone(&active);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                active.store(false, Ordering::Relaxed);
+            })
+        };
+
+        let (sender, _receiver) = mpsc::channel();
+        let start = Instant::now();
+        assert!(ReplayTrackingClient::replay(&options, &sender, &active).unwrap());
+        stopper.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
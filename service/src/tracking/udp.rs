@@ -0,0 +1,76 @@
+use std::{
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    time,
+};
+
+use log::{error, info, warn};
+
+use crate::{
+    error::BridgeError,
+    tracking::{client::TrackingClient, response::TrackingResponse},
+};
+
+// Phone app sending one text frame per UDP packet after it gets the start message
+pub(crate) struct UdpStream<'a> {
+    pub local_port: u16,
+    pub destination_address: String,
+    pub start_message: &'a str,
+    // Sent when the client is stopped
+    pub stop_message: Option<&'a str>,
+}
+
+impl UdpStream<'_> {
+    pub fn run<C: TrackingClient>(
+        &self,
+        parse: impl Fn(&str) -> Result<TrackingResponse, BridgeError>,
+        sender: &Sender<TrackingResponse>,
+        active: &AtomicBool,
+    ) {
+        let address = format!("{}:{}", "0.0.0.0", self.local_port);
+        let socket = match UdpSocket::bind(&address) {
+            Ok(socket) => socket,
+            Err(error) => {
+                error!("Unable to listen on {}: {}", address, error);
+                return;
+            }
+        };
+        let _ = socket.set_read_timeout(Some(time::Duration::new(2, 0)));
+        info!("UDP server listening on {address}");
+
+        let mut buf = [0; 8192];
+        let mut handshake_needed = true;
+
+        while active.load(Ordering::Relaxed) {
+            // Repeated after every timeout, so the stream resumes when the phone reconnects
+            if handshake_needed {
+                match socket.send_to(self.start_message.as_bytes(), &self.destination_address) {
+                    Ok(_) => info!("UDP message sent to {}", self.destination_address),
+                    Err(error) => warn!("Unable to request tracking data: {}", error),
+                }
+                handshake_needed = false;
+            }
+
+            match socket.recv_from(&mut buf) {
+                Ok((amt, _src)) => match std::str::from_utf8(&buf[..amt]) {
+                    Ok(data) => match parse(data) {
+                        Ok(tracking_response) => C::send(sender, tracking_response),
+                        Err(error) => warn!("{}", error),
+                    },
+                    Err(error) => warn!("Unable to decode frame: {}", error),
+                },
+                Err(error) => {
+                    warn!("Unnable to receive: {}", error);
+                    handshake_needed = true;
+                }
+            }
+        }
+
+        if let Some(stop_message) = self.stop_message {
+            let _ = socket.send_to(stop_message.as_bytes(), &self.destination_address);
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use std::{
+    collections::HashMap,
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    time,
+};
+
+use log::{error, info, warn};
+
+use crate::{
+    tracking::{
+        client::{TrackingClient, TrackingOptions},
+        response::{Cords, Shape, TrackingResponse},
+    },
+    utils::{get_current_timestamp, normalize_shape_name},
+};
+
+#[derive(Debug, PartialEq)]
+pub enum OscArgument {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Bool(bool),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub arguments: Vec<OscArgument>,
+}
+
+impl OscMessage {
+    fn string(&self, index: usize) -> Option<&str> {
+        match self.arguments.get(index)? {
+            OscArgument::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn float(&self, index: usize) -> Option<f64> {
+        match self.arguments.get(index)? {
+            OscArgument::Float(f) => Some(*f as f64),
+            OscArgument::Int(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    fn int(&self, index: usize) -> Option<i32> {
+        match self.arguments.get(index)? {
+            OscArgument::Int(i) => Some(*i),
+            OscArgument::Float(f) => Some(*f as i32),
+            _ => None,
+        }
+    }
+}
+
+fn read_padded_string(
+    buf: &[u8],
+    offset: &mut usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let rest = buf.get(*offset..).ok_or("Unexpected end of packet")?;
+    let length = rest
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or("Unterminated string")?;
+    let string = std::str::from_utf8(&rest[..length])?.to_string();
+    // String + null terminator, padded to 4 bytes
+    *offset += (length + 4) & !3;
+    Ok(string)
+}
+
+fn read_bytes<const N: usize>(
+    buf: &[u8],
+    offset: &mut usize,
+) -> Result<[u8; N], Box<dyn std::error::Error>> {
+    let bytes = buf
+        .get(*offset..)
+        .and_then(|rest| rest.get(..N))
+        .ok_or("Unexpected end of packet")?;
+    *offset += N;
+    Ok(bytes.try_into()?)
+}
+
+// Sizes come from the sender, so they can't be trusted to fit in the packet
+fn read_size(buf: &[u8], offset: &mut usize) -> Result<usize, Box<dyn std::error::Error>> {
+    Ok(u32::from_be_bytes(read_bytes(buf, offset)?) as usize)
+}
+
+fn skip(buf: &[u8], offset: &mut usize, size: usize) -> Result<(), Box<dyn std::error::Error>> {
+    *offset = offset
+        .checked_add(size)
+        .filter(|end| *end <= buf.len())
+        .ok_or("Size exceeds packet size")?;
+    Ok(())
+}
+
+fn parse_osc_message(buf: &[u8]) -> Result<OscMessage, Box<dyn std::error::Error>> {
+    let mut offset = 0;
+    let address = read_padded_string(buf, &mut offset)?;
+    if !address.starts_with('/') {
+        return Err(format!("Invalid OSC address: {}", address).into());
+    }
+
+    let mut arguments = Vec::new();
+    if offset >= buf.len() {
+        // Type tag string is optional for messages without arguments
+        return Ok(OscMessage { address, arguments });
+    }
+
+    let type_tags = read_padded_string(buf, &mut offset)?;
+    for tag in type_tags.chars().skip_while(|c| *c == ',') {
+        let argument = match tag {
+            'i' => OscArgument::Int(i32::from_be_bytes(read_bytes(buf, &mut offset)?)),
+            'f' => OscArgument::Float(f32::from_be_bytes(read_bytes(buf, &mut offset)?)),
+            'd' => OscArgument::Float(f64::from_be_bytes(read_bytes(buf, &mut offset)?) as f32),
+            'h' => OscArgument::Int(i64::from_be_bytes(read_bytes(buf, &mut offset)?) as i32),
+            's' | 'S' => OscArgument::String(read_padded_string(buf, &mut offset)?),
+            'T' => OscArgument::Bool(true),
+            'F' => OscArgument::Bool(false),
+            'N' | 'I' => continue,
+            't' => {
+                read_bytes::<8>(buf, &mut offset)?;
+                continue;
+            }
+            'b' => {
+                let size = read_size(buf, &mut offset)?;
+                let padded = size.checked_add(3).ok_or("Blob exceeds packet size")? & !3;
+                skip(buf, &mut offset, padded)?;
+                continue;
+            }
+            _ => return Err(format!("Unsupported OSC type tag: {}", tag).into()),
+        };
+        arguments.push(argument);
+    }
+
+    Ok(OscMessage { address, arguments })
+}
+
+// Flattens bundles (which may be nested) into a list of messages
+pub fn parse_osc_packet(buf: &[u8]) -> Result<Vec<OscMessage>, Box<dyn std::error::Error>> {
+    if !buf.starts_with(b"#bundle\0") {
+        return Ok(vec![parse_osc_message(buf)?]);
+    }
+
+    let mut messages = Vec::new();
+    // "#bundle\0" + 8 bytes of time tag
+    let mut offset = 16;
+    while offset < buf.len() {
+        let size = read_size(buf, &mut offset)?;
+        let element = buf
+            .get(offset..)
+            .and_then(|rest| rest.get(..size))
+            .ok_or("Bundle element exceeds packet size")?;
+        messages.append(&mut parse_osc_packet(element)?);
+        skip(buf, &mut offset, size)?;
+    }
+    Ok(messages)
+}
+
+// Unity quaternion to euler angles in degrees (Z, X, Y rotation order)
+fn quaternion_to_euler(x: f64, y: f64, z: f64, w: f64) -> Cords {
+    let pitch = (2.0 * (w * x - y * z)).clamp(-1.0, 1.0).asin();
+    let yaw = (2.0 * (w * y + x * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+    let roll = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (x * x + z * z));
+    Cords {
+        x: pitch.to_degrees(),
+        y: yaw.to_degrees(),
+        z: roll.to_degrees(),
+    }
+}
+
+#[derive(Default)]
+pub struct VmcState {
+    face_found: Option<bool>,
+    rotation: Option<Cords>,
+    position: Option<Cords>,
+    blend_shapes: HashMap<String, f64>,
+}
+
+impl VmcState {
+    // Returns a response once the sender applies the blendshapes of the current frame
+    pub fn apply(&mut self, message: &OscMessage) -> Option<TrackingResponse> {
+        match message.address.as_str() {
+            "/VMC/Ext/OK" => {
+                let loaded = message.int(0).unwrap_or(1) == 1;
+                // Tracking status is only sent by VMC protocol v2.7+
+                let tracking = message.int(3).is_none_or(|status| status == 1);
+                self.face_found = Some(loaded && tracking);
+            }
+            "/VMC/Ext/Bone/Pos" if message.string(0) == Some("Head") => {
+                if let (Some(x), Some(y), Some(z)) =
+                    (message.float(1), message.float(2), message.float(3))
+                {
+                    // Metres to centimetres, close to what iPhone apps report
+                    self.position = Some(Cords {
+                        x: x * 100.0,
+                        y: y * 100.0,
+                        z: z * 100.0,
+                    });
+                }
+                if let (Some(x), Some(y), Some(z), Some(w)) = (
+                    message.float(4),
+                    message.float(5),
+                    message.float(6),
+                    message.float(7),
+                ) {
+                    self.rotation = Some(quaternion_to_euler(x, y, z, w));
+                }
+            }
+            "/VMC/Ext/Blend/Val" => {
+                if let (Some(name), Some(value)) = (message.string(0), message.float(1)) {
+                    self.blend_shapes.insert(normalize_shape_name(name), value);
+                }
+            }
+            "/VMC/Ext/Blend/Apply" => return Some(self.response()),
+            _ => {}
+        }
+        None
+    }
+
+    fn response(&self) -> TrackingResponse {
+        TrackingResponse {
+            timestamp: get_current_timestamp(),
+            hotkey: 0,
+            // Senders without /VMC/Ext/OK are treated as tracking once a head bone arrives
+            face_found: self.face_found.unwrap_or(self.rotation.is_some()),
+            rotation: self.rotation.clone().unwrap_or_default(),
+            position: self.position.clone().unwrap_or_default(),
+            eye_left: Cords::default(),
+            blend_shapes: self
+                .blend_shapes
+                .iter()
+                .map(|(k, v)| Shape {
+                    k: k.clone(),
+                    v: *v,
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct VmcTrackingClient;
+
+impl VmcTrackingClient {
+    pub const PORT: u16 = 39539;
+
+    pub fn listen(address: &str, sender: Sender<TrackingResponse>, active: Arc<AtomicBool>) {
+        let socket = match UdpSocket::bind(address) {
+            Ok(socket) => socket,
+            Err(error) => {
+                error!("Unable to listen on {}: {}", address, error);
+                return;
+            }
+        };
+        let _ = socket.set_read_timeout(Some(time::Duration::new(2, 0)));
+        info!("VMC receiver listening on {}", address);
+
+        let mut buf = [0; 65535];
+        let mut state = VmcState::default();
+
+        while active.load(Ordering::Relaxed) {
+            match socket.recv_from(&mut buf) {
+                Ok((amt, _src)) => match parse_osc_packet(&buf[..amt]) {
+                    Ok(messages) => {
+                        for message in &messages {
+                            if let Some(response) = state.apply(message) {
+                                Self::send(&sender, response);
+                            }
+                        }
+                    }
+                    Err(error) => warn!("Unable to parse OSC packet: {}", error),
+                },
+                Err(error) => {
+                    warn!("Unnable to receive: {}", error)
+                }
+            }
+        }
+    }
+}
+
+impl TrackingClient for VmcTrackingClient {
+    // VMC is pushed by the sender, so the phone IP is not needed
+    fn run(
+        _ip: String,
+        _options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        Self::listen(&format!("0.0.0.0:{}", Self::PORT), sender, active);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn padded(bytes: &[u8]) -> Vec<u8> {
+        let mut padded = bytes.to_vec();
+        padded.push(0);
+        padded.resize((padded.len() + 3) & !3, 0);
+        padded
+    }
+
+    fn message(address: &str, tags: &str, arguments: &[&[u8]]) -> Vec<u8> {
+        let mut message = padded(address.as_bytes());
+        message.extend(padded(format!(",{tags}").as_bytes()));
+        for argument in arguments {
+            message.extend_from_slice(argument);
+        }
+        message
+    }
+
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        for element in elements {
+            bundle.extend((element.len() as u32).to_be_bytes());
+            bundle.extend(element);
+        }
+        bundle
+    }
+
+    fn blend(name: &str, value: f32) -> Vec<u8> {
+        message(
+            "/VMC/Ext/Blend/Val",
+            "sf",
+            &[&padded(name.as_bytes()), &value.to_be_bytes()],
+        )
+    }
+
+    #[test]
+    fn parses_nested_bundles_and_skips_blobs() {
+        let with_blob = message(
+            "/blob",
+            "bi",
+            &[&5u32.to_be_bytes(), b"abcde\0\0\0", &7i32.to_be_bytes()],
+        );
+        let packet = bundle(&[blend("A", 0.5), bundle(&[with_blob, blend("B", 1.0)])]);
+
+        let messages = parse_osc_packet(&packet).unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                OscMessage {
+                    address: "/VMC/Ext/Blend/Val".into(),
+                    arguments: vec![OscArgument::String("A".into()), OscArgument::Float(0.5)],
+                },
+                OscMessage {
+                    address: "/blob".into(),
+                    arguments: vec![OscArgument::Int(7)],
+                },
+                OscMessage {
+                    address: "/VMC/Ext/Blend/Val".into(),
+                    arguments: vec![OscArgument::String("B".into()), OscArgument::Float(1.0)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let value = blend("A", 0.5);
+        assert!(parse_osc_packet(&value[..value.len() - 2]).is_err());
+
+        let mut packet = bundle(&[value]);
+        packet.truncate(packet.len() - 4);
+        assert!(parse_osc_packet(&packet).is_err());
+
+        // Sizes that would overflow when padded or added to the offset
+        let blob = message("/blob", "b", &[&u32::MAX.to_be_bytes()]);
+        assert!(parse_osc_packet(&blob).is_err());
+        let mut packet = bundle(&[]);
+        packet.extend(u32::MAX.to_be_bytes());
+        assert!(parse_osc_packet(&packet).is_err());
+    }
+
+    fn floats(values: &[f32]) -> Vec<Vec<u8>> {
+        values
+            .iter()
+            .map(|value| value.to_be_bytes().to_vec())
+            .collect()
+    }
+
+    fn bone(name: &str, values: &[f32; 7]) -> Vec<u8> {
+        let mut arguments = vec![padded(name.as_bytes())];
+        arguments.extend(floats(values));
+        let arguments: Vec<&[u8]> = arguments.iter().map(Vec::as_slice).collect();
+        message("/VMC/Ext/Bone/Pos", "sfffffff", &arguments)
+    }
+
+    // Head at 10, 20, 30 cm turned 90 degrees around Y, the root and other bones are ignored
+    fn frame_packet() -> Vec<u8> {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let root = floats(&[5.0, 5.0, 5.0]);
+        bundle(&[
+            message("/VMC/Ext/OK", "i", &[&1i32.to_be_bytes()]),
+            message(
+                "/VMC/Ext/Root/Pos",
+                "sfff",
+                &[&padded(b"root"), &root[0], &root[1], &root[2]],
+            ),
+            bone("Neck", &[1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0]),
+            bone("Head", &[0.1, 0.2, 0.3, 0.0, half, 0.0, half]),
+            blend("jawOpen", 0.25),
+            blend("EyeBlink_L", 1.0),
+            message("/VMC/Ext/Blend/Apply", "", &[]),
+        ])
+    }
+
+    fn assert_frame(response: &TrackingResponse) {
+        assert!(response.face_found);
+        assert!((response.position.x - 10.0).abs() < 1e-4);
+        assert!((response.position.y - 20.0).abs() < 1e-4);
+        assert!((response.position.z - 30.0).abs() < 1e-4);
+        assert!(response.rotation.x.abs() < 1e-3);
+        assert!((response.rotation.y - 90.0).abs() < 1e-3);
+        assert!(response.rotation.z.abs() < 1e-3);
+
+        let mut shapes: Vec<_> = response
+            .blend_shapes
+            .iter()
+            .map(|shape| (shape.k.as_str(), shape.v))
+            .collect();
+        shapes.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(shapes, vec![("EyeBlinkLeft", 1.0), ("JawOpen", 0.25)]);
+    }
+
+    #[test]
+    fn maps_blend_shapes_and_head_bone() {
+        let mut state = VmcState::default();
+        let responses: Vec<_> = parse_osc_packet(&frame_packet())
+            .unwrap()
+            .iter()
+            .filter_map(|message| state.apply(message))
+            .collect();
+        assert_eq!(responses.len(), 1);
+        assert_frame(&responses[0]);
+    }
+
+    #[test]
+    fn listens_for_datagrams() {
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = format!("127.0.0.1:{}", port);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let active = Arc::new(AtomicBool::new(true));
+        let listener = {
+            let address = address.clone();
+            let active = Arc::clone(&active);
+            std::thread::spawn(move || VmcTrackingClient::listen(&address, sender, active))
+        };
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let deadline = std::time::Instant::now() + time::Duration::from_secs(5);
+        let response = loop {
+            // Datagrams sent before the listener is bound are lost
+            socket.send_to(&frame_packet(), &address).unwrap();
+            match receiver.recv_timeout(time::Duration::from_millis(100)) {
+                Ok(response) => break response,
+                Err(_) if std::time::Instant::now() < deadline => {}
+                Err(error) => panic!("No frame received: {}", error),
+            }
+        };
+        active.store(false, Ordering::Relaxed);
+        listener.join().unwrap();
+
+        assert_frame(&response);
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    net::UdpSocket,
+    net::{IpAddr, UdpSocket},
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::Sender,
@@ -8,17 +8,85 @@ use std::{
     time,
 };
 
-use log::warn;
+use log::{error, info, warn};
 
-use crate::tracking::{client::TrackingClient, response::TrackingResponse};
+use crate::tracking::{
+    client::{TrackingClient, TrackingOptions},
+    response::TrackingResponse,
+};
+
+pub type TrackingParser = fn(&[u8]) -> Result<TrackingResponse, Box<dyn std::error::Error>>;
 
 pub struct VTubeStudioTrackingClient;
 
-impl TrackingClient for VTubeStudioTrackingClient {
-    fn run(ip: String, sender: Sender<TrackingResponse>, active: Arc<AtomicBool>) {
-        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+impl VTubeStudioTrackingClient {
+    const PHONE_PORT: u16 = 21412;
+
+    fn parse(buf: &[u8]) -> Result<TrackingResponse, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_slice::<TrackingResponse>(buf)?)
+    }
+
+    // Phones broadcast their presence on the LAN, the last one heard is used
+    fn discover_phone(discovery_socket: &UdpSocket) -> Option<IpAddr> {
+        let mut buf = [0; 4096];
+        let mut phone = None;
+        while let Ok((amt, src)) = discovery_socket.recv_from(&mut buf) {
+            if let Ok(message) = serde_json::from_slice::<serde_json::Value>(&buf[..amt]) {
+                if message["messageType"].is_string() {
+                    phone = Some(src.ip());
+                }
+            }
+        }
+        phone
+    }
+
+    // Also used by apps emulating the VTubeStudio iPhone protocol
+    pub(crate) fn request_tracking(
+        ip: String,
+        options: TrackingOptions,
+        parse: TrackingParser,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        // Port 0 is any free port, fixed one is useful for firewall rules
+        let socket = match UdpSocket::bind(("0.0.0.0", options.vts_listen_port)) {
+            Ok(socket) => socket,
+            Err(error) => {
+                error!(
+                    "Unable to listen on port {}: {}",
+                    options.vts_listen_port, error
+                );
+                return;
+            }
+        };
         let _ = socket.set_read_timeout(Some(time::Duration::new(2, 0)));
-        let port = socket.local_addr().unwrap().port();
+        let port = socket
+            .local_addr()
+            .map_or(options.vts_listen_port, |address| address.port());
+        info!("Waiting for tracking data on port {}", port);
+
+        // Without phone IP it's discovered and updated when the phone changes its address
+        let mut phone: Option<IpAddr> = ip.parse().ok();
+        let discovery_socket = if ip.is_empty() {
+            // Phone IP has to be set when something else listens to the port
+            match UdpSocket::bind(("0.0.0.0", Self::PHONE_PORT))
+                .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+            {
+                Ok(socket) => {
+                    info!("Searching for phones on port {}", Self::PHONE_PORT);
+                    Some(socket)
+                }
+                Err(error) => {
+                    error!("Unable to search for phones: {}", error);
+                    return;
+                }
+            }
+        } else {
+            if phone.is_none() {
+                warn!("Invalid phone IP: {}", ip);
+            }
+            None
+        };
 
         let mut buf = [0; 4096];
 
@@ -36,18 +104,27 @@ impl TrackingClient for VTubeStudioTrackingClient {
             if next_time <= time::Instant::now() {
                 next_time = time::Instant::now() + time::Duration::from_secs(1);
 
-                match socket.send_to(request_traking.as_bytes(), format!("{:}:21412", ip)) {
-                    Ok(_) => {
-                        // nice
+                if let Some(discovered) = discovery_socket.as_ref().and_then(Self::discover_phone) {
+                    if phone != Some(discovered) {
+                        info!("Found phone: {}", discovered);
+                        phone = Some(discovered);
                     }
-                    Err(error) => {
-                        warn!("Unable to request tracking data: {}", error) // Maybe reconnect
+                }
+
+                if let Some(phone) = phone {
+                    match socket.send_to(request_traking.as_bytes(), (phone, Self::PHONE_PORT)) {
+                        Ok(_) => {
+                            // nice
+                        }
+                        Err(error) => {
+                            warn!("Unable to request tracking data: {}", error) // Maybe reconnect
+                        }
                     }
                 }
             }
 
             match socket.recv_from(&mut buf) {
-                Ok((amt, _src)) => match serde_json::from_slice::<TrackingResponse>(&buf[..amt]) {
+                Ok((amt, _src)) => match parse(&buf[..amt]) {
                     Ok(data) => Self::send(&sender, data),
                     Err(error) => {
                         warn!("Unnable to deserialize: {}", error)
@@ -60,3 +137,14 @@ impl TrackingClient for VTubeStudioTrackingClient {
         }
     }
 }
+
+impl TrackingClient for VTubeStudioTrackingClient {
+    fn run(
+        ip: String,
+        options: TrackingOptions,
+        sender: Sender<TrackingResponse>,
+        active: Arc<AtomicBool>,
+    ) {
+        Self::request_tracking(ip, options, Self::parse, sender, active);
+    }
+}
@@ -0,0 +1,1095 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::Write,
+    path::Path,
+    sync::LazyLock,
+};
+
+use evalexpr::{Context, ContextWithMutableVariables, HashMapContext, Node, Value};
+use log::warn;
+use regex::{Captures, Regex};
+use serde::{
+    de::{
+        value::{MapAccessDeserializer, SeqAccessDeserializer},
+        MapAccess, SeqAccess, Visitor,
+    },
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    calibration::{insert_calibrated_info, Calibration},
+    curve::CurveConfig,
+    error::BridgeError,
+    filter::{FilterConfig, Filters},
+    functions::insert_functions,
+    recording::RecordReader,
+    tracking::response::TrackingResponse,
+};
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    Yaml,
+    // Transformations are an array of tables: [[transformation]]
+    Toml,
+}
+
+impl ConfigFormat {
+    // Unknown extensions are read as JSON
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, schemars::JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+#[schemars(rename = "Transformation")]
+pub(crate) struct CalcFn {
+    #[schemars(
+        description = "Name of the VTubeStudio parameter, custom parameters are created with it"
+    )]
+    pub name: String,
+    #[schemars(
+        description = "evalexpr expression with tracking variables like JawOpen, HeadRotX, ifm.JawOpen or Wave1000"
+    )]
+    pub func: String,
+    #[schemars(description = "Minimum of a custom parameter")]
+    pub min: f64,
+    #[schemars(description = "Maximum of a custom parameter")]
+    pub max: f64,
+    #[schemars(description = "Value of a custom parameter without tracking")]
+    pub default_value: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(
+        description = "Response curve applied to the value of func, [input, output] points or an object with points and interpolation"
+    )]
+    pub curve: Option<CurveConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(description = "Smoothing of the value between frames: ema, oneEuro or kalman")]
+    pub filter: Option<FilterConfig>,
+}
+
+// Config is either a list of transformations or a map with let variables and transformations
+#[derive(Debug, Default)]
+pub(crate) struct ConfigFile {
+    pub variables: BTreeMap<String, String>,
+    pub transformations: Vec<CalcFn>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema, Debug)]
+#[schemars(rename = "ConfigWithVariables")]
+struct ConfigTable {
+    #[serde(rename = "let", default)]
+    #[schemars(
+        description = "Named expressions evaluated once per frame before transformations, which use them as variables"
+    )]
+    variables: BTreeMap<String, String>,
+    // [[transformation]] in TOML
+    #[serde(alias = "transformation", default)]
+    transformations: Vec<CalcFn>,
+}
+
+impl<'de> Deserialize<'de> for ConfigFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ConfigVisitor;
+
+        impl<'de> Visitor<'de> for ConfigVisitor {
+            type Value = ConfigFile;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter
+                    .write_str("a list of transformations or a map with let and transformations")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<ConfigFile, A::Error> {
+                let transformations = Vec::deserialize(SeqAccessDeserializer::new(seq))?;
+                Ok(ConfigFile {
+                    variables: BTreeMap::new(),
+                    transformations,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ConfigFile, A::Error> {
+                let table = ConfigTable::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(ConfigFile {
+                    variables: table.variables,
+                    transformations: table.transformations,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(ConfigVisitor)
+    }
+}
+
+impl schemars::JsonSchema for ConfigFile {
+    fn schema_name() -> String {
+        "Config".into()
+    }
+
+    fn json_schema(generator: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        schemars::schema::SchemaObject {
+            subschemas: Some(Box::new(schemars::schema::SubschemaValidation {
+                any_of: Some(vec![
+                    generator.subschema_for::<Vec<CalcFn>>(),
+                    generator.subschema_for::<ConfigTable>(),
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+pub(crate) fn read_config_file(path: &str) -> Result<ConfigFile, BridgeError> {
+    let config = fs::read_to_string(path)
+        .map_err(|error| BridgeError::Config(format!("Unable to read {}: {}", path, error)))?;
+    let config_file = match ConfigFormat::from_path(path) {
+        ConfigFormat::Json => serde_json::from_str(&config[..]).map_err(|error| error.to_string()),
+        ConfigFormat::Yaml => serde_yaml::from_str(&config[..]).map_err(|error| error.to_string()),
+        ConfigFormat::Toml => toml::from_str(&config[..]).map_err(|error| error.to_string()),
+    };
+    config_file.map_err(|error| BridgeError::Config(format!("Invalid {}: {}", path, error)))
+}
+
+static HISTORY_CALL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(prev|delta)\s*\(\s*([A-Za-z_][A-Za-z0-9_.]*)\s*\)").unwrap());
+
+// evalexpr functions get values instead of names, so prev(X) becomes the variable prev.X
+// set from the previous frame and delta(X) becomes (X - prev.X)
+pub(crate) fn expand_history(func: &str) -> String {
+    HISTORY_CALL
+        .replace_all(func, |captures: &Captures| match &captures[1] {
+            "prev" => format!("prev.{}", &captures[2]),
+            _ => format!("({0} - prev.{0})", &captures[2]),
+        })
+        .into_owned()
+}
+
+// JSON Schema of JSON and YAML configs
+pub fn config_schema() -> String {
+    let mut schema = schemars::schema_for!(ConfigFile);
+    schema.schema.metadata().title = Some("Sandoitchi Bridge transformations".into());
+    serde_json::to_string_pretty(&schema).unwrap()
+}
+
+// Names of let variables in the order of evaluation, each one after the variables it uses.
+// Error is the path of a cycle: ["a", "b", "a"].
+pub(crate) fn variable_order(
+    dependencies: &BTreeMap<String, Vec<String>>,
+) -> Result<Vec<String>, Vec<String>> {
+    fn visit(
+        name: &str,
+        dependencies: &BTreeMap<String, Vec<String>>,
+        path: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), Vec<String>> {
+        if order.iter().any(|sorted| sorted == name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|visiting| visiting == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(cycle);
+        }
+
+        path.push(name.to_string());
+        for dependency in &dependencies[name] {
+            if dependencies.contains_key(dependency) {
+                visit(dependency, dependencies, path, order)?;
+            }
+        }
+        path.pop();
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    for name in dependencies.keys() {
+        visit(name, dependencies, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+// Errors repeat every frame, so each parameter is logged at most once in the interval
+const WARNING_INTERVAL_MS: u128 = 5000;
+
+// Values of previous frames for prev(), delta(), dt and self_prev, and states of filters
+#[derive(Debug, Default)]
+pub struct History {
+    time_ms: Option<u128>,
+    values: HashMap<String, Value>,
+    // dt and prev.X of the last recorded frame, set again until the source sends the next one
+    last_inserted: Vec<(String, Value)>,
+    // Last sent value of every parameter
+    outputs: HashMap<String, f64>,
+    filters: Filters,
+    // Time of the last logged error of every parameter or variable
+    warnings: HashMap<String, u128>,
+}
+
+impl History {
+    // Config reload starts over, logged errors are still not repeated
+    pub fn clear(&mut self) {
+        self.time_ms = None;
+        self.values.clear();
+        self.last_inserted.clear();
+        self.outputs.clear();
+        self.filters.clear();
+    }
+
+    pub fn warn(&mut self, error: &BridgeError, time_ms: u128) {
+        let key = match error {
+            BridgeError::Expression { parameter, .. } => parameter.clone(),
+            error => error.to_string(),
+        };
+        let logged = self
+            .warnings
+            .get(&key)
+            .is_some_and(|last| time_ms.saturating_sub(*last) < WARNING_INTERVAL_MS);
+        if !logged {
+            warn!("{}", error);
+            self.warnings.insert(key, time_ms);
+        }
+    }
+}
+
+pub struct Variable {
+    pub name: String,
+    pub func: String,
+    pub node: Node,
+}
+
+pub struct Transformation {
+    pub name: String,
+    pub func: String,
+    pub min: f64,
+    pub max: f64,
+    pub default_value: f64,
+    pub node: Node,
+    // Let variables used directly or through other variables
+    pub variables: HashSet<String>,
+    pub curve: Option<CurveConfig>,
+    pub filter: Option<FilterConfig>,
+}
+
+impl Transformation {
+    pub fn evaluate(&self, context: &HashMapContext) -> Result<f64, BridgeError> {
+        let value = self.evaluate_unclamped(context)?;
+        Ok(value.clamp(-1000000.0, 1000000.0))
+    }
+
+    // self_prev is the value sent on the previous frame, defaultValue on the first one.
+    // Value is smoothed by the filter before clamping and kept for the next frame.
+    pub fn evaluate_with_history(
+        &self,
+        context: &mut HashMapContext,
+        history: &mut History,
+        time_ms: u128,
+    ) -> Result<f64, BridgeError> {
+        let self_prev = history
+            .outputs
+            .get(&self.name)
+            .copied()
+            .unwrap_or(self.default_value);
+        context
+            .set_value("self_prev".into(), self_prev.into())
+            .map_err(|error| BridgeError::expression(&self.name, error))?;
+
+        let mut value = self.evaluate_unclamped(context)?;
+        if let Some(filter) = &self.filter {
+            value = history.filters.apply(&self.name, filter, value, time_ms);
+        }
+        let value = value.clamp(-1000000.0, 1000000.0);
+        history.outputs.insert(self.name.clone(), value);
+        Ok(value)
+    }
+
+    fn evaluate_unclamped(&self, context: &HashMapContext) -> Result<f64, BridgeError> {
+        // Ints too, like "if(JawOpen > 0.1, 1, 0)"
+        let mut value = self
+            .node
+            .eval_with_context(context)
+            .and_then(|value| value.as_number())
+            .map_err(|error| BridgeError::expression(&self.name, error))?;
+        if let Some(curve) = &self.curve {
+            value = curve.apply(value);
+        }
+        // e.g. 0 / 0 with dt of the first frame, VTubeStudio would reject the whole message
+        if value.is_nan() {
+            return Err(BridgeError::expression(&self.name, "Value is not a number"));
+        }
+        Ok(value)
+    }
+}
+
+pub struct TransformationConfig {
+    // Let variables in the order of evaluation
+    pub variables: Vec<Variable>,
+    pub transformations: Vec<Transformation>,
+    // Cycle durations of used WaveN and PingPongN
+    pub timestamps: HashSet<u64>,
+    // Variables used with prev() or delta()
+    pub history: HashSet<String>,
+}
+
+impl TransformationConfig {
+    pub fn load(path: &str) -> Result<Self, BridgeError> {
+        let config_file = read_config_file(path)?;
+
+        let mut timestamps = HashSet::new();
+        let mut nodes = BTreeMap::new();
+        for (name, func) in config_file.variables {
+            timestamps.extend(extract_wave_pingpong_numbers(&func));
+
+            let node = evalexpr::build_operator_tree(&expand_history(&func))
+                .map_err(|error| BridgeError::expression(&name, error))?;
+            nodes.insert(name, (func, node));
+        }
+
+        // prev.x is a value of the previous frame, so "let x = prev(x) + 1" is not a cycle
+        let dependencies = nodes
+            .iter()
+            .map(|(name, (_, node))| (name.clone(), read_identifiers(node)))
+            .collect();
+        let order = variable_order(&dependencies).map_err(|cycle| {
+            BridgeError::Config(format!("Cycle in let variables: {}", cycle.join(" -> ")))
+        })?;
+        let variables: Vec<Variable> = order
+            .into_iter()
+            .map(|name| {
+                let (func, node) = nodes.remove(&name).unwrap();
+                Variable { name, func, node }
+            })
+            .collect();
+
+        let mut transformations = Vec::new();
+        for func in config_file.transformations.into_iter() {
+            timestamps.extend(extract_wave_pingpong_numbers(&func.func));
+
+            let node = evalexpr::build_operator_tree(&expand_history(&func.func))
+                .map_err(|error| BridgeError::expression(&func.name, error))?;
+            if let Some(curve) = &func.curve {
+                curve.validate().map_err(|error| {
+                    BridgeError::Config(format!("Invalid curve of {}: {}", func.name, error))
+                })?;
+            }
+            if let Some(filter) = &func.filter {
+                filter.validate().map_err(|error| {
+                    BridgeError::Config(format!("Invalid filter of {}: {}", func.name, error))
+                })?;
+            }
+
+            // Variables come after their dependencies, so going backwards finds all of them in one pass
+            let mut used: HashSet<String> = used_identifiers(&node).collect();
+            for variable in variables.iter().rev() {
+                if used.contains(&variable.name) {
+                    used.extend(used_identifiers(&variable.node));
+                }
+            }
+            used.retain(|name| variables.iter().any(|variable| variable.name == *name));
+
+            transformations.push(Transformation {
+                name: func.name,
+                func: func.func,
+                min: func.min,
+                max: func.max,
+                default_value: func.default_value,
+                node,
+                variables: used,
+                curve: func.curve,
+                filter: func.filter,
+            });
+        }
+
+        let history = variables
+            .iter()
+            .map(|variable| &variable.node)
+            .chain(
+                transformations
+                    .iter()
+                    .map(|transformation| &transformation.node),
+            )
+            .flat_map(|node| node.iter_read_variable_identifiers())
+            .filter_map(|identifier| identifier.strip_prefix("prev."))
+            .map(str::to_string)
+            .collect();
+
+        Ok(Self {
+            variables,
+            transformations,
+            timestamps,
+            history,
+        })
+    }
+
+    // Sets dt and prev.X, before the let variables which may use them. Without a previous frame
+    // dt is 0 and prev.X is the current value, or 0 for a let variable that is not calculated yet.
+    // Without a new frame of the current source, e.g. when only a secondary one has sent something,
+    // its values are the same as on the last frame and so are dt and prev.X.
+    // A name already holding a value of another type is left as is and its error is returned.
+    pub fn insert_history(
+        &self,
+        context: &mut HashMapContext,
+        history: &History,
+        time_ms: u128,
+        new_frame: bool,
+    ) -> Result<(), BridgeError> {
+        if !new_frame && !history.last_inserted.is_empty() {
+            let mut result = Ok(());
+            for (name, value) in &history.last_inserted {
+                if let Err(error) = context.set_value(name.clone(), value.clone()) {
+                    result = Err(BridgeError::expression(name, error));
+                }
+            }
+            return result;
+        }
+
+        let dt = history
+            .time_ms
+            .map_or(0.0, |last| time_ms.saturating_sub(last) as f64 / 1000.0);
+        let mut result = context
+            .set_value("dt".into(), dt.into())
+            .map_err(|error| BridgeError::expression("dt", error));
+
+        for name in &self.history {
+            let value = history
+                .values
+                .get(name)
+                .or_else(|| context.get_value(name))
+                .cloned()
+                .unwrap_or(Value::Float(0.0));
+            let name = format!("prev.{name}");
+            if let Err(error) = context.set_value(name.clone(), value) {
+                result = Err(BridgeError::expression(&name, error));
+            }
+        }
+        result
+    }
+
+    // Keeps the values of a new frame of the current source for prev() of the next one
+    pub fn record_history(&self, context: &HashMapContext, history: &mut History, time_ms: u128) {
+        history.time_ms = Some(time_ms);
+        history.last_inserted = ["dt".to_string()]
+            .into_iter()
+            .chain(self.history.iter().map(|name| format!("prev.{name}")))
+            .filter_map(|name| Some((name.clone(), context.get_value(&name)?.clone())))
+            .collect();
+        for name in &self.history {
+            if let Some(value) = context.get_value(name) {
+                history.values.insert(name.clone(), value.clone());
+            }
+        }
+    }
+
+    // Variables failing to evaluate are left unset, so only parameters using them are skipped.
+    // A name already holding a value of another type can't be set either, that error is returned.
+    pub fn insert_variables(&self, context: &mut HashMapContext) -> Result<(), BridgeError> {
+        let mut result = Ok(());
+        for variable in &self.variables {
+            if let Ok(value) = variable.node.eval_with_context(context) {
+                if let Err(error) = context.set_value(variable.name.clone(), value) {
+                    result = Err(BridgeError::expression(&variable.name, error));
+                }
+            }
+        }
+        result
+    }
+
+    // Text is searched in the function and in the let variables it uses
+    pub fn uses(&self, transformation: &Transformation, text: &str) -> bool {
+        transformation.func.contains(text)
+            || self.variables.iter().any(|variable| {
+                transformation.variables.contains(&variable.name) && variable.func.contains(text)
+            })
+    }
+
+    // Parameters failing to evaluate are logged and skipped, e.g. when a secondary source
+    // has not sent anything yet
+    pub fn evaluate(
+        &self,
+        context: &mut HashMapContext,
+        history: &mut History,
+        time_ms: u128,
+    ) -> Vec<(&str, f64)> {
+        self.transformations
+            .iter()
+            .filter_map(|transformation| {
+                match transformation.evaluate_with_history(context, history, time_ms) {
+                    Ok(value) => Some((transformation.name.as_str(), value)),
+                    Err(error) => {
+                        history.warn(&error, time_ms);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn read_identifiers(node: &Node) -> Vec<String> {
+    node.iter_read_variable_identifiers()
+        .map(str::to_string)
+        .collect()
+}
+
+// Read identifiers with prev.X counted as X
+fn used_identifiers(node: &Node) -> impl Iterator<Item = String> + '_ {
+    node.iter_read_variable_identifiers()
+        .map(|identifier| identifier.strip_prefix("prev.").unwrap_or(identifier))
+        .map(str::to_string)
+}
+
+pub(crate) fn extract_wave_pingpong_numbers(input: &str) -> HashSet<u64> {
+    let re = Regex::new(r"(Wave|PingPong)(\d+)").unwrap();
+
+    re.captures_iter(input)
+        .filter_map(|caps| caps.get(2)?.as_str().parse::<u64>().ok())
+        .collect()
+}
+
+fn calculate_ppw(total_milliseconds: u128, cycle_duration_ms: u64) -> (f64, f64) {
+    let milliseconds_in_cycle = total_milliseconds as u64 % cycle_duration_ms;
+
+    let milliseconds = milliseconds_in_cycle as f64;
+
+    let ping_pong = milliseconds / cycle_duration_ms as f64;
+
+    let half_cycle = (cycle_duration_ms as f64) / 2.0;
+    let wave = if milliseconds < half_cycle {
+        milliseconds / half_cycle
+    } else {
+        2.0 - (milliseconds / half_cycle)
+    };
+
+    (ping_pong, wave)
+}
+
+// A name already holding a value of another type is left as is and its error is returned
+pub fn insert_cyclic_info(
+    context: &mut HashMapContext,
+    used_timestamps: &HashSet<u64>,
+    total_milliseconds: u128,
+) -> Result<(), BridgeError> {
+    let mut result = Ok(());
+    for v in used_timestamps {
+        let (ping_pong, wave) = calculate_ppw(total_milliseconds, *v);
+        for (name, value) in [
+            (format!("PingPong{v}"), ping_pong),
+            (format!("Wave{v}"), wave),
+        ] {
+            if let Err(error) = context.set_value(name.clone(), value.into()) {
+                result = Err(BridgeError::expression(&name, error));
+            }
+        }
+    }
+    result
+}
+
+// Blendshapes and head of a frame, the values set by insert_tracking_info besides FaceFound
+pub(crate) fn tracking_values(raw_data: &TrackingResponse) -> impl Iterator<Item = (&str, f64)> {
+    raw_data
+        .blend_shapes
+        .iter()
+        .map(|shape| (shape.k.as_str(), shape.v))
+        .chain([
+            ("HeadPosX", raw_data.position.x),
+            ("HeadPosY", raw_data.position.y),
+            ("HeadPosZ", raw_data.position.z),
+            ("HeadRotX", raw_data.rotation.x),
+            ("HeadRotY", raw_data.rotation.y),
+            ("HeadRotZ", raw_data.rotation.z),
+        ])
+}
+
+// Values of a source are set as "HeadRotX" or with a prefix as "ifm.HeadRotX".
+// A name already holding a value of another type is left as is and its error is returned.
+pub fn insert_tracking_info(
+    context: &mut HashMapContext,
+    prefix: Option<&str>,
+    raw_data: &TrackingResponse,
+) -> Result<(), BridgeError> {
+    let key = |name: &str| match prefix {
+        Some(prefix) => format!("{prefix}.{name}"),
+        None => name.to_string(),
+    };
+
+    let values = tracking_values(raw_data)
+        .chain([("FaceFound", if raw_data.face_found { 1.0 } else { 0.0 })]);
+
+    let mut result = Ok(());
+    for (name, value) in values {
+        let name = key(name);
+        if let Err(error) = context.set_value(name.clone(), value.into()) {
+            result = Err(BridgeError::Protocol(format!(
+                "Unable to set {}: {}",
+                name, error
+            )));
+        }
+    }
+    result
+}
+
+// Writes a CSV row of parameter values per recorded frame, returns the count of rows.
+// Variables are set the same way as in the plugin, the first recorded source is primary.
+// Errors are logged like in the plugin and cells of parameters failing to evaluate are left empty.
+pub fn evaluate_recording(
+    config: &TransformationConfig,
+    calibration: &Calibration,
+    reader: &mut RecordReader,
+    output: &mut impl Write,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    write!(output, "received_ms,source,FaceFound")?;
+    for transformation in &config.transformations {
+        write!(output, ",{}", transformation.name)?;
+    }
+    writeln!(output)?;
+
+    let mut sources: Vec<(String, TrackingResponse)> = Vec::new();
+    let mut history = History::default();
+    let mut rows = 0;
+    let mut context = HashMapContext::new();
+    insert_functions(&mut context);
+    while let Some(record) = reader.read()? {
+        let received_ms = record.received_ms;
+        match sources.iter_mut().find(|(name, _)| *name == record.source) {
+            Some((_, response)) => *response = record.response,
+            None => sources.push((record.source.clone(), record.response)),
+        }
+
+        let time_ms = received_ms as u128;
+        context.clear_variables();
+        let mut errors = Vec::new();
+        errors.push(insert_cyclic_info(
+            &mut context,
+            &config.timestamps,
+            time_ms,
+        ));
+        for (name, response) in &sources {
+            errors.push(insert_tracking_info(&mut context, Some(name), response));
+        }
+        let primary = &sources[0].1;
+        errors.push(insert_tracking_info(&mut context, None, primary));
+        errors.push(insert_calibrated_info(&mut context, calibration, primary));
+        // Frames of secondary sources leave the values of the primary as they were
+        let new_frame = record.source == sources[0].0;
+        errors.push(config.insert_history(&mut context, &history, time_ms, new_frame));
+        errors.push(config.insert_variables(&mut context));
+        for error in errors.into_iter().filter_map(Result::err) {
+            history.warn(&error, time_ms);
+        }
+
+        write!(
+            output,
+            "{},{},{}",
+            received_ms, record.source, primary.face_found as u8
+        )?;
+        for transformation in &config.transformations {
+            match transformation.evaluate_with_history(&mut context, &mut history, time_ms) {
+                Ok(value) => write!(output, ",{}", value)?,
+                Err(error) => {
+                    history.warn(&error, time_ms);
+                    write!(output, ",")?
+                }
+            }
+        }
+        writeln!(output)?;
+        if new_frame {
+            config.record_history(&context, &mut history, time_ms);
+        }
+        rows += 1;
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::{
+        calibration::VariableCalibration,
+        recording::Record,
+        tracking::response::{Cords, Shape},
+    };
+
+    fn load(name: &str, config: &str) -> TransformationConfig {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, config).unwrap();
+        TransformationConfig::load(path.to_str().unwrap()).unwrap()
+    }
+
+    fn response(jaw_open: f64) -> TrackingResponse {
+        TrackingResponse {
+            timestamp: 0,
+            hotkey: 0,
+            face_found: true,
+            rotation: Cords::default(),
+            position: Cords::default(),
+            eye_left: Cords::default(),
+            blend_shapes: vec![Shape {
+                k: "JawOpen".into(),
+                v: jaw_open,
+            }],
+        }
+    }
+
+    // Variables and transformations of the config file as one value to compare
+    fn read(name: &str, config: &str) -> serde_json::Value {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, config).unwrap();
+        let config_file = read_config_file(path.to_str().unwrap()).unwrap();
+        serde_json::json!({
+            "let": config_file.variables,
+            "transformations": config_file.transformations,
+        })
+    }
+
+    #[test]
+    fn reads_same_config_in_every_format() {
+        let json = read(
+            "sandoitchi_format.json",
+            r#"{
+                "let": {"open": "JawOpen - MouthClose"},
+                "transformations": [
+                    {"name": "MouthOpen", "func": "open * 2", "min": 0, "max": 1, "defaultValue": 0.5,
+                     "curve": [[0, 0], [1, 1]], "filter": {"type": "ema", "alpha": 0.5}},
+                    {"name": "Sway", "func": "Wave1000", "min": -1, "max": 1, "defaultValue": 0}
+                ]
+            }"#,
+        );
+        let yaml = read(
+            "sandoitchi_format.yaml",
+            r#"
+let:
+  open: JawOpen - MouthClose
+transformations:
+  - name: MouthOpen
+    func: open * 2
+    min: 0
+    max: 1
+    defaultValue: 0.5
+    curve: [[0, 0], [1, 1]]
+    filter:
+      type: ema
+      alpha: 0.5
+  - name: Sway
+    func: Wave1000
+    min: -1
+    max: 1
+    defaultValue: 0
+"#,
+        );
+        let toml = read(
+            "sandoitchi_format.toml",
+            r#"
+[let]
+open = "JawOpen - MouthClose"
+
+[[transformation]]
+name = "MouthOpen"
+func = "open * 2"
+min = 0
+max = 1
+defaultValue = 0.5
+curve = [[0, 0], [1, 1]]
+filter = { type = "ema", alpha = 0.5 }
+
+[[transformation]]
+name = "Sway"
+func = "Wave1000"
+min = -1
+max = 1
+defaultValue = 0
+"#,
+        );
+        assert_eq!(json["transformations"][1]["min"], -1.0);
+        assert_eq!(json, yaml);
+        assert_eq!(json, toml);
+
+        // JSON and YAML may be a plain list of transformations
+        let list = read(
+            "sandoitchi_format_list.yml",
+            "- {name: Sway, func: Wave1000, min: -1, max: 1, defaultValue: 0}",
+        );
+        assert_eq!(list["let"], serde_json::json!({}));
+        assert_eq!(list["transformations"][0], json["transformations"][1]);
+    }
+
+    fn dependencies(variables: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        variables
+            .iter()
+            .map(|(name, uses)| {
+                let uses = uses.iter().map(|used| used.to_string()).collect();
+                (name.to_string(), uses)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn orders_variables_after_their_dependencies() {
+        let order = variable_order(&dependencies(&[
+            ("a", &["c", "JawOpen"]),
+            ("b", &["a", "c"]),
+            ("c", &["d"]),
+            ("d", &[]),
+        ]))
+        .unwrap();
+        assert_eq!(order, ["d", "c", "a", "b"]);
+
+        assert_eq!(
+            variable_order(&dependencies(&[
+                ("a", &["b"]),
+                ("b", &["c"]),
+                ("c", &["a"])
+            ])),
+            Err(vec!["a".into(), "b".into(), "c".into(), "a".into()])
+        );
+        assert_eq!(
+            variable_order(&dependencies(&[("a", &["a"])])),
+            Err(vec!["a".into(), "a".into()])
+        );
+    }
+
+    #[test]
+    fn evaluates_variables_in_order() {
+        let config = load(
+            "sandoitchi_let_order.json",
+            r#"{
+                "let": {"a": "b * 2", "b": "c + 1", "c": "JawOpen", "count": "prev(count) + 1"},
+                "transformations": [
+                    {"name": "Open", "func": "a", "min": 0, "max": 10, "defaultValue": 0}
+                ]
+            }"#,
+        );
+        let names: Vec<&str> = config
+            .variables
+            .iter()
+            .map(|variable| variable.name.as_str())
+            .collect();
+        assert_eq!(names, ["c", "b", "a", "count"]);
+        assert_eq!(
+            config.transformations[0].variables,
+            HashSet::from(["a".into(), "b".into(), "c".into()])
+        );
+
+        let mut context = HashMapContext::new();
+        insert_tracking_info(&mut context, None, &response(0.5)).unwrap();
+        config.insert_variables(&mut context).unwrap();
+        assert_eq!(config.transformations[0].evaluate(&context).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn rejects_cycles_of_variables() {
+        let path = env::temp_dir().join("sandoitchi_let_cycle.json");
+        fs::write(
+            &path,
+            r#"{"let": {"a": "b + 1", "b": "a * 2"}, "transformations": []}"#,
+        )
+        .unwrap();
+        let error = TransformationConfig::load(path.to_str().unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Config error: Cycle in let variables: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn expands_prev_and_delta() {
+        assert_eq!(
+            expand_history("prev(JawOpen) + delta( ifm.HeadRotX ) * previous(JawOpen)"),
+            "prev.JawOpen + (ifm.HeadRotX - prev.ifm.HeadRotX) * previous(JawOpen)"
+        );
+        assert_eq!(expand_history("prev(1 + x)"), "prev(1 + x)");
+    }
+
+    // Parameter values of one frame of the primary source like in the plugin
+    fn history_frame(
+        config: &TransformationConfig,
+        history: &mut History,
+        jaw_open: f64,
+        time_ms: u128,
+        new_frame: bool,
+    ) -> HashMap<String, f64> {
+        let mut context = HashMapContext::new();
+        insert_tracking_info(&mut context, None, &response(jaw_open)).unwrap();
+        config
+            .insert_history(&mut context, history, time_ms, new_frame)
+            .unwrap();
+        config.insert_variables(&mut context).unwrap();
+        let values = config
+            .evaluate(&mut context, history, time_ms)
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        if new_frame {
+            config.record_history(&context, history, time_ms);
+        }
+        values
+    }
+
+    fn assert_values(values: &HashMap<String, f64>, expected: &[(&str, f64)]) {
+        let mut names: Vec<&str> = values.keys().map(String::as_str).collect();
+        names.sort();
+        let mut expected_names: Vec<&str> = expected.iter().map(|(name, _)| *name).collect();
+        expected_names.sort();
+        assert_eq!(names, expected_names);
+        for (name, value) in expected {
+            assert!((values[*name] - value).abs() < 1e-9, "{name}: {values:?}");
+        }
+    }
+
+    #[test]
+    fn sets_prev_delta_dt_and_self_prev() {
+        let config = load(
+            "sandoitchi_history.json",
+            r#"{
+                "let": {"count": "prev(count) + 1"},
+                "transformations": [
+                    {"name": "Prev", "func": "prev(JawOpen)", "min": 0, "max": 1, "defaultValue": 0},
+                    {"name": "Delta", "func": "delta(JawOpen)", "min": -1, "max": 1, "defaultValue": 0},
+                    {"name": "Speed", "func": "delta(JawOpen) / dt", "min": -10, "max": 10, "defaultValue": 0},
+                    {"name": "Self", "func": "self_prev + 1", "min": 0, "max": 10, "defaultValue": 0.5},
+                    {"name": "Count", "func": "count", "min": 0, "max": 10, "defaultValue": 0}
+                ]
+            }"#,
+        );
+        assert_eq!(
+            config.history,
+            HashSet::from(["JawOpen".into(), "count".into()])
+        );
+        let mut history = History::default();
+
+        // Without a previous frame prev is the current value and dt is 0, so Speed is 0 / 0
+        assert_values(
+            &history_frame(&config, &mut history, 0.2, 1000, true),
+            &[("Prev", 0.2), ("Delta", 0.0), ("Self", 1.5), ("Count", 1.0)],
+        );
+        assert_values(
+            &history_frame(&config, &mut history, 0.5, 1100, true),
+            &[
+                ("Prev", 0.2),
+                ("Delta", 0.3),
+                ("Speed", 3.0),
+                ("Self", 2.5),
+                ("Count", 2.0),
+            ],
+        );
+        // Only a secondary source has sent something
+        assert_values(
+            &history_frame(&config, &mut history, 0.5, 1120, false),
+            &[
+                ("Prev", 0.2),
+                ("Delta", 0.3),
+                ("Speed", 3.0),
+                ("Self", 3.5),
+                ("Count", 2.0),
+            ],
+        );
+        // dt is counted from the last frame of the source
+        assert_values(
+            &history_frame(&config, &mut history, 0.6, 1200, true),
+            &[
+                ("Prev", 0.5),
+                ("Delta", 0.1),
+                ("Speed", 1.0),
+                ("Self", 4.5),
+                ("Count", 3.0),
+            ],
+        );
+        // dt is 0 for frames at the same time
+        assert_values(
+            &history_frame(&config, &mut history, 0.6, 1200, true),
+            &[("Prev", 0.6), ("Delta", 0.0), ("Self", 5.5), ("Count", 4.0)],
+        );
+
+        // Config reload starts over
+        history.clear();
+        assert_values(
+            &history_frame(&config, &mut history, 0.9, 1300, false),
+            &[("Prev", 0.9), ("Delta", 0.0), ("Self", 1.5), ("Count", 1.0)],
+        );
+    }
+
+    #[test]
+    fn evaluates_int_results() {
+        let config = load(
+            "sandoitchi_int_config.json",
+            r#"[
+                {"name": "Open", "func": "if(JawOpen > 0.1, 1, 0)", "min": 0, "max": 1, "defaultValue": 0},
+                {"name": "One", "func": "1", "min": 0, "max": 1, "defaultValue": 0},
+                {"name": "Missing", "func": "vmc2.JawOpen", "min": 0, "max": 1, "defaultValue": 0}
+            ]"#,
+        );
+        let mut history = History::default();
+        let mut context = HashMapContext::new();
+        insert_tracking_info(&mut context, None, &response(0.5)).unwrap();
+
+        // Failing parameter is skipped
+        let values = config.evaluate(&mut context, &mut history, 1000);
+        assert_eq!(values, [("Open", 1.0), ("One", 1.0)]);
+
+        insert_tracking_info(&mut context, None, &response(0.0)).unwrap();
+        assert_eq!(config.transformations[0].evaluate(&context).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn evaluates_recording_to_csv() {
+        let config = load(
+            "sandoitchi_eval_config.json",
+            r#"[
+                {"name": "Jaw", "func": "JawOpen * 2", "min": 0, "max": 2, "defaultValue": 0},
+                {"name": "Vmc", "func": "vmc2.JawOpen", "min": 0, "max": 1, "defaultValue": 0},
+                {"name": "Cal", "func": "JawOpen_cal", "min": -1, "max": 1, "defaultValue": 0},
+                {"name": "Dt", "func": "dt * 1000", "min": 0, "max": 100, "defaultValue": 0}
+            ]"#,
+        );
+        let calibration = Calibration {
+            variables: BTreeMap::from([(
+                "JawOpen".to_string(),
+                VariableCalibration { neutral: 0.5 },
+            )]),
+        };
+
+        let recording: Vec<String> = [("ifm", 1000, 0.5), ("vmc2", 1010, 0.25), ("ifm", 1020, 1.0)]
+            .into_iter()
+            .map(|(source, received_ms, jaw_open)| {
+                serde_json::to_string(&Record {
+                    source: source.into(),
+                    received_ms,
+                    response: response(jaw_open),
+                })
+                .unwrap()
+            })
+            .collect();
+        let path = env::temp_dir().join("sandoitchi_eval_recording.jsonl");
+        fs::write(&path, recording.join("\n")).unwrap();
+        let mut reader = RecordReader::open(path.to_str().unwrap()).unwrap();
+
+        let mut output = Vec::new();
+        let rows = evaluate_recording(&config, &calibration, &mut reader, &mut output).unwrap();
+        assert_eq!(rows, 3);
+        // The first source is primary, Vmc is empty until vmc2 has sent a frame.
+        // dt is the time between frames of the primary.
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "received_ms,source,FaceFound,Jaw,Vmc,Cal,Dt\n\
+             1000,ifm,1,1,,0,0\n\
+             1010,vmc2,1,1,0.25,0,0\n\
+             1020,ifm,1,2,0.25,0.5,20\n"
+        );
+    }
+}
@@ -14,3 +14,23 @@ pub fn get_current_timestamp_ms() -> u128 {
         .unwrap()
         .as_millis();
 }
+
+pub fn capitalize_first_letter(string: &str) -> String {
+    let mut characters = string.chars();
+    match characters.next() {
+        None => String::new(),
+        Some(first) => first.to_uppercase().collect::<String>() + characters.as_str(),
+    }
+}
+
+// "eyeBlink_L" -> "EyeBlinkLeft"
+pub fn normalize_shape_name(name: &str) -> String {
+    let key = capitalize_first_letter(name);
+    if let Some(base) = key.strip_suffix("_L") {
+        format!("{base}Left")
+    } else if let Some(base) = key.strip_suffix("_R") {
+        format!("{base}Right")
+    } else {
+        key
+    }
+}
@@ -1,3 +1,5 @@
 pub mod requests;
 pub mod responses;
 pub mod plugin;
+#[cfg(feature = "mock-vts")]
+pub mod mock;
\ No newline at end of file
@@ -0,0 +1,290 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::ErrorKind,
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use tungstenite::Message;
+
+use crate::utils::get_current_timestamp_ms;
+
+// What the plugin did with the mock so far
+#[derive(Debug, Clone, Default)]
+pub struct MockState {
+    pub authenticated: bool,
+    // Types of all received requests in order
+    pub requests: Vec<String>,
+    pub created_parameters: Vec<String>,
+    pub injection_count: usize,
+    pub last_face_found: Option<bool>,
+    // Last injected value of every parameter
+    pub parameter_values: HashMap<String, f64>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: MockState,
+    // Error ids to answer the next requests of the type with
+    errors: HashMap<String, VecDeque<u16>>,
+}
+
+// Fake VTubeStudio API with the subset of requests used by the plugin.
+// It announces its port like VTubeStudio does, so the plugin finds it when port 8001 is not taken.
+pub struct MockVTubeStudio {
+    port: u16,
+    shared: Arc<Mutex<Shared>>,
+    active: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockVTubeStudio {
+    pub const TOKEN: &str = "MockAuthenticationToken";
+    const DISCOVERY_PORT: u16 = 47779;
+    const DISCOVERY_INTERVAL: Duration = Duration::from_secs(1);
+
+    // "127.0.0.1:0" picks a free port
+    pub fn start(address: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let active = Arc::new(AtomicBool::new(true));
+
+        let handle = {
+            let shared = Arc::clone(&shared);
+            let active = Arc::clone(&active);
+            thread::spawn(move || Self::serve(listener, port, shared, active))
+        };
+
+        Ok(Self {
+            port,
+            shared,
+            active,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn state(&self) -> MockState {
+        self.shared.lock().unwrap().state.clone()
+    }
+
+    // The next request of the type is answered with APIError instead, e.g. ("ParameterCreationRequest", 352)
+    pub fn fail_next(&self, message_type: &str, error_id: u16) {
+        self.shared
+            .lock()
+            .unwrap()
+            .errors
+            .entry(message_type.to_string())
+            .or_default()
+            .push_back(error_id);
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.active.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    fn serve(
+        listener: TcpListener,
+        port: u16,
+        shared: Arc<Mutex<Shared>>,
+        active: Arc<AtomicBool>,
+    ) {
+        let discovery_socket = UdpSocket::bind("127.0.0.1:0").ok();
+        let mut next_discovery = Instant::now();
+        let mut connections = Vec::new();
+
+        while active.load(Ordering::Relaxed) {
+            if next_discovery <= Instant::now() {
+                next_discovery = Instant::now() + Self::DISCOVERY_INTERVAL;
+                if let Some(socket) = &discovery_socket {
+                    let _ = socket.send_to(
+                        Self::discovery_message(port).as_bytes(),
+                        ("127.0.0.1", Self::DISCOVERY_PORT),
+                    );
+                }
+            }
+
+            match listener.accept() {
+                Ok((stream, _address)) => {
+                    let shared = Arc::clone(&shared);
+                    let active = Arc::clone(&active);
+                    connections.push(thread::spawn(move || {
+                        Self::handle_connection(stream, shared, active)
+                    }));
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(_) => break,
+            }
+        }
+
+        for connection in connections {
+            let _ = connection.join();
+        }
+    }
+
+    fn handle_connection(stream: TcpStream, shared: Arc<Mutex<Shared>>, active: Arc<AtomicBool>) {
+        let _ = stream.set_nonblocking(false);
+        // Timeout lets the connection notice that the mock is stopped
+        let _ = stream.set_read_timeout(Some(Duration::from_millis(100)));
+        let Ok(mut websocket) = tungstenite::accept(stream) else {
+            return;
+        };
+
+        while active.load(Ordering::Relaxed) {
+            match websocket.read() {
+                Ok(Message::Text(text)) => {
+                    let response = Self::respond(&text, &mut shared.lock().unwrap());
+                    if websocket.send(Message::text(response.to_string())).is_err() {
+                        return;
+                    }
+                }
+                Ok(Message::Close(_)) => return,
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(error))
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => return,
+            }
+        }
+
+        let _ = websocket.close(None);
+        let _ = websocket.flush();
+    }
+
+    fn respond(text: &str, shared: &mut Shared) -> Value {
+        let Ok(request) = serde_json::from_str::<Value>(text) else {
+            return Self::error_message("", 2, "Invalid JSON");
+        };
+        let request_id = request["requestID"].as_str().unwrap_or_default();
+        let message_type = request["messageType"].as_str().unwrap_or_default();
+        let data = &request["data"];
+        shared.state.requests.push(message_type.to_string());
+
+        if let Some(error_id) = shared
+            .errors
+            .get_mut(message_type)
+            .and_then(VecDeque::pop_front)
+        {
+            return Self::error_message(request_id, error_id, "Error requested in mock");
+        }
+
+        let state = &mut shared.state;
+        match message_type {
+            "APIStateRequest" => Self::message(
+                request_id,
+                "APIStateResponse",
+                json!({
+                    "active": true,
+                    "vTubeStudioVersion": "mock",
+                    "currentSessionAuthenticated": state.authenticated,
+                }),
+            ),
+            "AuthenticationTokenRequest" => Self::message(
+                request_id,
+                "AuthenticationTokenResponse",
+                json!({ "authenticationToken": Self::TOKEN }),
+            ),
+            "AuthenticationRequest" => {
+                state.authenticated = data["authenticationToken"] == Self::TOKEN;
+                Self::message(
+                    request_id,
+                    "AuthenticationResponse",
+                    json!({
+                        "authenticated": state.authenticated,
+                        "reason": if state.authenticated { "Token valid" } else { "Token invalid" },
+                    }),
+                )
+            }
+            _ if !state.authenticated => {
+                Self::error_message(request_id, 8, "Request requires authentication")
+            }
+            "ParameterCreationRequest" => {
+                let name = data["parameterName"].as_str().unwrap_or_default();
+                state.created_parameters.push(name.to_string());
+                Self::message(
+                    request_id,
+                    "ParameterCreationResponse",
+                    json!({ "parameterName": name }),
+                )
+            }
+            "InjectParameterDataRequest" => {
+                let values = data["parameterValues"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                if values.is_empty() {
+                    return Self::error_message(request_id, 450, "No parameter values");
+                }
+                for value in values {
+                    if let (Some(id), Some(value)) = (value["id"].as_str(), value["value"].as_f64())
+                    {
+                        state.parameter_values.insert(id.to_string(), value);
+                    }
+                }
+                state.injection_count += 1;
+                state.last_face_found = data["faceFound"].as_bool();
+                Self::message(request_id, "InjectParameterDataResponse", json!({}))
+            }
+            _ => Self::error_message(request_id, 3, "Unknown message type"),
+        }
+    }
+
+    fn message(request_id: &str, message_type: &str, data: Value) -> Value {
+        json!({
+            "apiName": "VTubeStudioPublicAPI",
+            "apiVersion": "1.0",
+            "timestamp": get_current_timestamp_ms() as u64,
+            "messageType": message_type,
+            "requestID": request_id,
+            "data": data,
+        })
+    }
+
+    fn error_message(request_id: &str, error_id: u16, message: &str) -> Value {
+        Self::message(
+            request_id,
+            "APIError",
+            json!({ "errorID": error_id, "message": message }),
+        )
+    }
+
+    fn discovery_message(port: u16) -> String {
+        Self::message(
+            "",
+            "VTubeStudioAPIStateBroadcast",
+            json!({
+                "active": true,
+                "port": port,
+                "instanceID": "MockVTubeStudio",
+                "windowTitle": "VTube Studio",
+            }),
+        )
+        .to_string()
+    }
+}
+
+impl Drop for MockVTubeStudio {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
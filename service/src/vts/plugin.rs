@@ -1,6 +1,6 @@
 use std::{
-    collections::{HashSet, VecDeque},
-    fs,
+    collections::VecDeque,
+    fs, mem,
     net::{TcpStream, UdpSocket},
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -10,16 +10,18 @@ use std::{
     time::{Duration, Instant},
 };
 
-use evalexpr::{
-    Context, ContextWithMutableVariables, HashMapContext, IterateVariablesContext, Node,
-};
+use evalexpr::{Context, ContextWithMutableVariables, HashMapContext, IterateVariablesContext};
 use log::{error, info, warn};
-use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
 
 use crate::{
+    calibration::{insert_calibrated_info, Calibration},
+    error::BridgeError,
+    functions::insert_functions,
     tracking::response::TrackingResponse,
+    transform::{insert_cyclic_info, insert_tracking_info, History, TransformationConfig},
     utils::{get_current_timestamp, get_current_timestamp_ms},
     vts::{requests, responses},
 };
@@ -47,24 +49,59 @@ struct VTSApiRequest<'a, T> {
     data: Option<T>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct CalcFn {
+struct TrackingSource {
     name: String,
-    func: String,
-    min: f64,
-    max: f64,
-    default_value: f64,
+    receiver: Receiver<TrackingResponse>,
+    last_response: Mutex<Option<TrackingResponse>>,
+    last_received: Mutex<Option<Instant>>,
+}
+
+impl TrackingSource {
+    fn is_recent(&self, timeout: Duration) -> bool {
+        self.last_received
+            .lock()
+            .unwrap()
+            .is_some_and(|time| time.elapsed() <= timeout)
+    }
+
+    fn is_tracking(&self, timeout: Duration) -> bool {
+        let recent = self.is_recent(timeout);
+        let face_found = self
+            .last_response
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|data| data.face_found);
+        recent && face_found
+    }
+
+    // Frozen frame of a source that stopped sending is not used anymore
+    fn expire(&self, timeout: Duration) {
+        if !self.is_recent(timeout) {
+            *self.last_response.lock().unwrap() = None;
+        }
+    }
 }
 
 pub struct VTubeStudioPlugin {
-    receiver: Receiver<TrackingResponse>,
+    // First one is primary, values of the current one are available without prefix
+    sources: Vec<TrackingSource>,
+    current_source: Mutex<usize>,
     transformation_cfg_path: String,
     config_reload_interval: Duration,
     face_search_timeout: u64,
-
+    // Zero disables switching to secondary sources
+    failover_timeout: Duration,
+    vts_host: String,
+    vts_port: u16,
+    calibration: Calibration,
+
+    // Both have the functions inserted once and are swapped after every frame sent
+    context: Mutex<HashMapContext>,
     last_context: LazyLock<Mutex<HashMapContext>>,
     last_context_timestamp: LazyLock<Mutex<u64>>,
+    // Previous frames for prev(), delta(), dt, self_prev and filters
+    history: Mutex<History>,
 }
 
 impl VTubeStudioPlugin {
@@ -73,38 +110,81 @@ impl VTubeStudioPlugin {
     const AFK_PARAMETERS: [&str; 3] = ["FaceFound", "Wave", "PingPong"];
 
     pub fn new(
-        receiver: Receiver<TrackingResponse>,
+        receivers: Vec<(String, Receiver<TrackingResponse>)>,
         transformation_cfg_path: String,
         config_reload_delay: u64,
         face_search_timeout: u64,
+        failover_timeout: u64,
     ) -> Self {
+        let sources = receivers
+            .into_iter()
+            .map(|(name, receiver)| TrackingSource {
+                name,
+                receiver,
+                last_response: Mutex::new(None),
+                last_received: Mutex::new(None),
+            })
+            .collect();
         let this = Self {
-            receiver,
+            sources,
+            current_source: Mutex::new(0),
             transformation_cfg_path,
             config_reload_interval: Duration::from_millis(config_reload_delay),
             face_search_timeout,
-            last_context: LazyLock::new(|| Mutex::new(HashMapContext::new())),
+            failover_timeout: Duration::from_millis(failover_timeout),
+            vts_host: "localhost".to_string(),
+            vts_port: 8001,
+            calibration: Calibration::default(),
+            context: Mutex::new(Self::new_context()),
+            last_context: LazyLock::new(|| Mutex::new(Self::new_context())),
             last_context_timestamp: LazyLock::new(|| Mutex::new(0)),
+            history: Mutex::new(History::default()),
         };
         return this;
     }
 
+    // Port is still discovered when VTubeStudio is not found at the endpoint
+    pub fn with_vts_endpoint(mut self, host: String, port: u16) -> Self {
+        self.vts_host = host;
+        self.vts_port = port;
+        self
+    }
+
+    // Neutral face subtracted in X_cal variables
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
     pub fn run(&self, active: Arc<AtomicBool>) {
+        // Later reloads with errors keep the previous config, without the first one there is nothing to send
+        let mut config = match self.load_cfg() {
+            Ok(config) => config,
+            Err(error) => {
+                error!("Unable to load cfg: {}", error);
+                return;
+            }
+        };
+
         while active.load(Ordering::Relaxed) {
             let flag = Arc::clone(&active);
 
-            let websocket = VTubeStudioPlugin::connect();
-            self.msg_loop(websocket, flag);
+            let Some(websocket) = self.connect(&active) else {
+                break;
+            };
+            self.msg_loop(websocket, &mut config, flag);
         }
+        info!("Plugin stopped");
     }
 
-    fn connect() -> WebSocket<MaybeTlsStream<TcpStream>> {
-        let mut port = "8001".to_string();
-        loop {
-            match tungstenite::connect(format!("ws://localhost:{}", port)) {
+    // None when stopped before VTubeStudio was found
+    fn connect(&self, active: &AtomicBool) -> Option<WebSocket<MaybeTlsStream<TcpStream>>> {
+        let mut port = self.vts_port.to_string();
+        while active.load(Ordering::Relaxed) {
+            match tungstenite::connect(format!("ws://{}:{}", self.vts_host, port)) {
                 Ok((websocket, _responce)) => {
-                    info!("Connected to local port:{}", port);
-                    return websocket;
+                    info!("Connected to {}:{}", self.vts_host, port);
+                    return Some(websocket);
                 }
                 Err(error) => {
                     warn!("{}", error);
@@ -120,6 +200,7 @@ impl VTubeStudioPlugin {
                 }
             }
         }
+        None
     }
 
     fn discover_port() -> Result<String, String> {
@@ -151,16 +232,16 @@ impl VTubeStudioPlugin {
     fn msg_loop(
         &self,
         mut websocket: WebSocket<MaybeTlsStream<TcpStream>>,
+        config: &mut TransformationConfig,
         active: Arc<AtomicBool>,
     ) {
         let mut msg_buffer: VecDeque<Message> = VecDeque::new();
         let mut token: Option<String> = fs::read_to_string("token").ok();
 
         let vts_status = VTubeStudioPlugin::req_status_msg();
-        let (mut precalc_funcs, mut used_timestamps, mut new_params) = self.precalc_cfg();
 
         msg_buffer.push_back(vts_status.clone());
-        msg_buffer.append(&mut new_params);
+        msg_buffer.append(&mut Self::new_params_msgs(config));
 
         let mut last_time_config_reloaded = Instant::now();
 
@@ -172,13 +253,21 @@ impl VTubeStudioPlugin {
             {
                 last_time_config_reloaded = Instant::now();
 
-                (precalc_funcs, used_timestamps, new_params) = self.precalc_cfg();
+                match self.load_cfg() {
+                    Ok(new_config) => {
+                        *config = new_config;
+                        self.history.lock().unwrap().clear();
 
-                msg_buffer.clear();
-                msg_buffer.push_back(vts_status.clone());
-                msg_buffer.append(&mut new_params);
+                        msg_buffer.clear();
+                        msg_buffer.push_back(vts_status.clone());
+                        msg_buffer.append(&mut Self::new_params_msgs(config));
 
-                info!("Config reloaded")
+                        info!("Config reloaded")
+                    }
+                    Err(error) => {
+                        error!("Unable to reload cfg, keeping the previous one: {}", error)
+                    }
+                }
             }
 
             if !dont_send {
@@ -191,10 +280,8 @@ impl VTubeStudioPlugin {
                         }
                     }
                 } else {
-                    let tracking_data = self.tracking_msg(&precalc_funcs, &used_timestamps);
-
-                    if tracking_data.is_some() {
-                        match websocket.send(tracking_data.unwrap()) {
+                    if let Some(tracking_data) = self.tracking_msg(config) {
+                        match websocket.send(tracking_data) {
                             Ok(_) => {}
                             Err(error) => {
                                 warn!("Unable to send tracking msg: {}", error);
@@ -210,89 +297,10 @@ impl VTubeStudioPlugin {
             match websocket.read() {
                 Ok(msg) => {
                     if msg.is_text() {
-                        let msg_value =
-                            serde_json::from_str::<Value>(msg.to_text().unwrap()).unwrap();
-
-                        match msg_value["messageType"].as_str() {
-                            Some(msg_type) => match msg_type {
-                                "APIError" => {
-                                    let err_data = serde_json::from_value::<
-                                        VTSApiResponse<responses::APIError>,
-                                    >(msg_value)
-                                    .unwrap();
-                                    // warn!("API error: {:?}", err_data.data);
-                                    match err_data.data.error_id {
-                                        8 => {
-                                            // msg_buffer.push_back(VtsPc::auth(&token));
-                                        }
-                                        51 => {
-                                            // POPUP ON SCREEN
-
-                                            // MAYBE
-                                            // DELAY
-                                            // msg_buffer.push_back(VtsPc::auth(&token));
-                                        }
-                                        352 => {
-                                            // custom parameter exist
-                                            msg_buffer.pop_front();
-                                        }
-                                        354 => {
-                                            // custom parameter is default
-                                            msg_buffer.pop_front();
-                                        }
-                                        450 => {
-                                            //No param data was sended
-                                        }
-                                        _ => error!("Unknown API error: {:?}", err_data.data),
-                                    }
-                                }
-                                "APIStateResponse" => {
-                                    let state_data =
-                                        serde_json::from_value::<
-                                            VTSApiResponse<responses::APIStateResponse>,
-                                        >(msg_value)
-                                        .unwrap();
-                                    msg_buffer.pop_front();
-                                    if !state_data.data.current_session_authenticated {
-                                        msg_buffer.push_front(VTubeStudioPlugin::auth(&token));
-                                    }
-                                }
-                                "AuthenticationTokenResponse" => {
-                                    let token_data =
-                                        serde_json::from_value::<
-                                            VTSApiResponse<responses::AuthenticationToken>,
-                                        >(msg_value)
-                                        .unwrap();
-
-                                    let _ =
-                                        fs::write("token", &token_data.data.authentication_token)
-                                            .map_err(|e| error!("Unable to save token: {:?}", e));
-                                    token = Some(token_data.data.authentication_token);
-                                    info!("Recived Token from VtubeStudio");
-                                    msg_buffer.pop_front();
-                                    msg_buffer.push_front(VTubeStudioPlugin::auth(&token));
-                                }
-                                "AuthenticationResponse" => {
-                                    let auth_data = serde_json::from_value::<
-                                        VTSApiResponse<responses::AuthenticationResponse>,
-                                    >(msg_value)
-                                    .unwrap();
-                                    msg_buffer.pop_front();
-                                    if !auth_data.data.authenticated {
-                                        token = None;
-                                        let _ = fs::remove_file("token")
-                                            .map_err(|e| error!("Unable to delete token: {:?}", e));
-                                        info!("Invalid Token, Requesting new...");
-                                        msg_buffer.push_back(VTubeStudioPlugin::auth(&token));
-                                    }
-                                }
-                                "InjectParameterDataResponse" => {}
-                                "ParameterCreationResponse" => {
-                                    msg_buffer.pop_front();
-                                }
-                                _ => warn!("Unknown message: {}", msg_value["messageType"]),
-                            },
-                            None => warn!("No type in responce: {}", msg.to_text().unwrap()),
+                        // Unexpected message is skipped, the connection goes on
+                        if let Err(error) = Self::handle_response(&msg, &mut msg_buffer, &mut token)
+                        {
+                            warn!("{}", error);
                         }
                         dont_send = false;
                     } else if msg.is_ping() || msg.is_pong() {
@@ -309,40 +317,109 @@ impl VTubeStudioPlugin {
                 }
             }
         }
-    }
-
-    fn calculate_ppw(&self, total_milliseconds: u128, cycle_duration_ms: u64) -> (f64, f64) {
-        let milliseconds_in_cycle = total_milliseconds as u64 % cycle_duration_ms;
 
-        let milliseconds = milliseconds_in_cycle as f64;
+        // Lets VTubeStudio know that the plugin is gone when it's stopped
+        let _ = websocket.close(None);
+        let _ = websocket.flush();
+    }
 
-        let ping_pong = milliseconds / cycle_duration_ms as f64;
+    fn parse_response<T: DeserializeOwned>(value: Value) -> Result<VTSApiResponse<T>, BridgeError> {
+        serde_json::from_value(value).map_err(|error| BridgeError::VtsApi(error.to_string()))
+    }
 
-        let half_cycle = (cycle_duration_ms as f64) / 2.0;
-        let wave = if milliseconds < half_cycle {
-            milliseconds / half_cycle
-        } else {
-            2.0 - (milliseconds / half_cycle)
+    fn handle_response(
+        msg: &Message,
+        msg_buffer: &mut VecDeque<Message>,
+        token: &mut Option<String>,
+    ) -> Result<(), BridgeError> {
+        let text = msg
+            .to_text()
+            .map_err(|error| BridgeError::VtsApi(error.to_string()))?;
+        let msg_value = serde_json::from_str::<Value>(text)
+            .map_err(|error| BridgeError::VtsApi(format!("{}: {}", error, text)))?;
+
+        let Some(msg_type) = msg_value["messageType"].as_str() else {
+            return Err(BridgeError::VtsApi(format!(
+                "No type in responce: {}",
+                text
+            )));
         };
+        match msg_type {
+            "APIError" => {
+                let err_data = Self::parse_response::<responses::APIError>(msg_value)?;
+                // warn!("API error: {:?}", err_data.data);
+                match err_data.data.error_id {
+                    8 => {
+                        // Session lost its authentication, the failed request is sent again after it
+                        msg_buffer.push_front(VTubeStudioPlugin::auth(token));
+                    }
+                    50 => {
+                        // Token request is still in front, so it's asked again
+                        warn!("Token request was denied in VtubeStudio");
+                    }
+                    51 => {
+                        // POPUP ON SCREEN
 
-        return (ping_pong, wave);
-    }
-
-    fn insert_cyclic_info(&self, context: &mut HashMapContext, used_timestamps: &HashSet<u64>) {
-        let total_milliseconds = get_current_timestamp_ms();
-        for v in used_timestamps {
-            let (ping_pong, wave) = self.calculate_ppw(total_milliseconds, *v);
-            context
-                .set_value(format!("PingPong{v}"), ping_pong.into())
-                .unwrap();
-            context.set_value(format!("Wave{v}"), wave.into()).unwrap();
+                        // MAYBE
+                        // DELAY
+                        // msg_buffer.push_back(VtsPc::auth(&token));
+                    }
+                    352 => {
+                        // custom parameter exist
+                        msg_buffer.pop_front();
+                    }
+                    354 => {
+                        // custom parameter is default
+                        msg_buffer.pop_front();
+                    }
+                    450 => {
+                        //No param data was sended
+                    }
+                    _ => error!("Unknown API error: {:?}", err_data.data),
+                }
+            }
+            "APIStateResponse" => {
+                let state_data = Self::parse_response::<responses::APIStateResponse>(msg_value)?;
+                msg_buffer.pop_front();
+                if !state_data.data.current_session_authenticated {
+                    msg_buffer.push_front(VTubeStudioPlugin::auth(token));
+                }
+            }
+            "AuthenticationTokenResponse" => {
+                let token_data = Self::parse_response::<responses::AuthenticationToken>(msg_value)?;
+
+                let _ = fs::write("token", &token_data.data.authentication_token)
+                    .map_err(|e| error!("Unable to save token: {:?}", e));
+                *token = Some(token_data.data.authentication_token);
+                info!("Recived Token from VtubeStudio");
+                msg_buffer.pop_front();
+                msg_buffer.push_front(VTubeStudioPlugin::auth(token));
+            }
+            "AuthenticationResponse" => {
+                let auth_data =
+                    Self::parse_response::<responses::AuthenticationResponse>(msg_value)?;
+                msg_buffer.pop_front();
+                if !auth_data.data.authenticated {
+                    *token = None;
+                    let _ = fs::remove_file("token")
+                        .map_err(|e| error!("Unable to delete token: {:?}", e));
+                    info!("Invalid Token, Requesting new...");
+                    msg_buffer.push_back(VTubeStudioPlugin::auth(token));
+                }
+            }
+            "InjectParameterDataResponse" => {}
+            "ParameterCreationResponse" => {
+                msg_buffer.pop_front();
+            }
+            _ => warn!("Unknown message: {}", msg_value["messageType"]),
         }
+        Ok(())
     }
 
     fn track_cyclic_info_only(
         &self,
-        precalc_funcs: &Vec<(String, String, Node)>,
-        used_timestamps: &HashSet<u64>,
+        config: &TransformationConfig,
+        history: &mut History,
         face_search_timeout: &u64,
     ) -> Option<Message> {
         let mut params: Vec<requests::TrackingParam> = Vec::new();
@@ -354,9 +431,8 @@ impl VTubeStudioPlugin {
 
             let mut face_found = mutex_context
                 .get_value("FaceFound")
-                .unwrap()
-                .as_float()
-                .unwrap();
+                .and_then(|value| value.as_float().ok())
+                .unwrap_or(0.0);
             if face_found == 1.0 {
                 let timestamp = self.last_context_timestamp.lock().unwrap();
                 let difference = *timestamp as f64 - get_current_timestamp() as f64;
@@ -368,22 +444,34 @@ impl VTubeStudioPlugin {
                         .unwrap();
                 }
             }
-            self.insert_cyclic_info(&mut mutex_context, used_timestamps);
+            let time_ms = get_current_timestamp_ms();
+            let errors = [
+                insert_cyclic_info(&mut mutex_context, &config.timestamps, time_ms),
+                // Nothing new was sent, so the history stays at the last frame
+                config.insert_history(&mut mutex_context, history, time_ms, false),
+                // Variables using Wave and PingPong change too
+                config.insert_variables(&mut mutex_context),
+            ];
+            for error in errors.into_iter().filter_map(Result::err) {
+                history.warn(&error, time_ms);
+            }
 
-            let cloned_context = mutex_context.clone();
-            for (key, func, node) in precalc_funcs {
+            for transformation in &config.transformations {
                 for parameter in Self::AFK_PARAMETERS {
-                    if func.contains(parameter) {
-                        params.push(requests::TrackingParam {
-                            id: key.as_str(),
-                            value: node
-                                .eval_with_context(&cloned_context)
-                                .unwrap()
-                                .as_float()
-                                .unwrap()
-                                .clamp(-1_000_000.0, 1_000_000.0),
-                            weight: Some(1.0),
-                        });
+                    if config.uses(transformation, parameter) {
+                        // Prefixed values of a source that has not sent anything yet are missing
+                        match transformation.evaluate_with_history(
+                            &mut mutex_context,
+                            history,
+                            time_ms,
+                        ) {
+                            Ok(value) => params.push(requests::TrackingParam {
+                                id: transformation.name.as_str(),
+                                value,
+                                weight: Some(1.0),
+                            }),
+                            Err(error) => history.warn(&error, time_ms),
+                        }
                         break;
                     }
                 }
@@ -408,75 +496,98 @@ impl VTubeStudioPlugin {
         Some(Message::text(request_string))
     }
 
-    fn tracking_msg(
-        &self,
-        precalc_funcs: &Vec<(String, String, Node)>,
-        used_timestamps: &HashSet<u64>,
-    ) -> Option<Message> {
-        let mut context = HashMapContext::new();
-
-        let mut binding = self.receiver.try_iter();
-        let it = binding.by_ref();
-
-        let raw_data = match it.last() {
-            Some(data) => data,
-            None => {
-                return self.track_cyclic_info_only(
-                    precalc_funcs,
-                    used_timestamps,
-                    &self.face_search_timeout,
-                );
-            }
+    // Primary is used while it tracks a face, otherwise the first secondary that does
+    fn select_source(&self) -> usize {
+        let index = if self.failover_timeout.is_zero() {
+            0
+        } else {
+            self.sources
+                .iter()
+                .position(|source| source.is_tracking(self.failover_timeout))
+                .unwrap_or(0)
         };
 
-        self.insert_cyclic_info(&mut context, used_timestamps);
-
-        for v in &raw_data.blend_shapes {
-            context.set_value(v.k.clone(), v.v.into()).unwrap();
+        let mut current_source = self.current_source.lock().unwrap();
+        if *current_source != index {
+            info!(
+                "Switched tracking source from {} to {}",
+                self.sources[*current_source].name, self.sources[index].name
+            );
+            *current_source = index;
         }
+        index
+    }
 
-        context
-            .set_value("HeadPosX".into(), raw_data.position.x.into())
-            .unwrap();
-        context
-            .set_value("HeadPosY".into(), raw_data.position.y.into())
-            .unwrap();
-        context
-            .set_value("HeadPosZ".into(), raw_data.position.z.into())
-            .unwrap();
+    fn tracking_msg(&self, config: &TransformationConfig) -> Option<Message> {
+        let mut context = self.context.lock().unwrap();
+        context.clear_variables();
+
+        let received: Vec<bool> = self
+            .sources
+            .iter()
+            .map(|source| {
+                source.expire(Duration::from_millis(self.face_search_timeout));
+                let Some(data) = source.receiver.try_iter().last() else {
+                    return false;
+                };
+                *source.last_response.lock().unwrap() = Some(data);
+                *source.last_received.lock().unwrap() = Some(Instant::now());
+                true
+            })
+            .collect();
+
+        let mut history = self.history.lock().unwrap();
+        if !received.contains(&true) {
+            return self.track_cyclic_info_only(config, &mut history, &self.face_search_timeout);
+        }
 
-        context
-            .set_value("HeadRotX".into(), raw_data.rotation.x.into())
-            .unwrap();
-        context
-            .set_value("HeadRotY".into(), raw_data.rotation.y.into())
-            .unwrap();
-        context
-            .set_value("HeadRotZ".into(), raw_data.rotation.z.into())
-            .unwrap();
+        let time_ms = get_current_timestamp_ms();
+        let mut errors = Vec::new();
+        errors.push(insert_cyclic_info(
+            &mut context,
+            &config.timestamps,
+            time_ms,
+        ));
+
+        for source in &self.sources {
+            if let Some(data) = source.last_response.lock().unwrap().as_ref() {
+                errors.push(insert_tracking_info(&mut context, Some(&source.name), data));
+            }
+        }
 
-        context
-            .set_value(
-                "FaceFound".into(),
-                (if raw_data.face_found { 1.0 } else { 0.0 }).into(),
-            )
-            .unwrap();
+        let index = self.select_source();
+        let current = self.sources[index].last_response.lock().unwrap();
+        // Selected source is stale while another one still sends
+        let Some(raw_data) = current.as_ref() else {
+            return self.track_cyclic_info_only(config, &mut history, &self.face_search_timeout);
+        };
+        errors.push(insert_tracking_info(&mut context, None, raw_data));
+        errors.push(insert_calibrated_info(
+            &mut context,
+            &self.calibration,
+            raw_data,
+        ));
+        // Only a secondary source may have sent something, then the values are the same as before
+        let new_frame = received[index];
+        errors.push(config.insert_history(&mut context, &history, time_ms, new_frame));
+        errors.push(config.insert_variables(&mut context));
+        for error in errors.into_iter().filter_map(Result::err) {
+            history.warn(&error, time_ms);
+        }
 
         let mut params: Vec<requests::TrackingParam> = Vec::new();
 
         if raw_data.face_found {
-            for (key, _, node) in precalc_funcs {
+            for (id, value) in config.evaluate(&mut context, &mut history, time_ms) {
                 params.push(requests::TrackingParam {
-                    id: key.as_str(),
-                    value: node
-                        .eval_with_context(&context)
-                        .unwrap()
-                        .as_float()
-                        .unwrap()
-                        .clamp(-1000000.0, 1000000.0),
+                    id,
+                    value,
                     weight: Some(1.0),
                 });
             }
+            if new_frame {
+                config.record_history(&context, &mut history, time_ms);
+            }
         }
 
         if params.is_empty() {
@@ -485,7 +596,7 @@ impl VTubeStudioPlugin {
 
         {
             let mut mutex_context = self.last_context.lock().unwrap();
-            *mutex_context = context;
+            mem::swap(&mut *mutex_context, &mut *context);
 
             let mut timestamp = self.last_context_timestamp.lock().unwrap();
             *timestamp = get_current_timestamp();
@@ -509,6 +620,12 @@ impl VTubeStudioPlugin {
         Some(Message::text(request_string))
     }
 
+    fn new_context() -> HashMapContext {
+        let mut context = HashMapContext::new();
+        insert_functions(&mut context);
+        context
+    }
+
     fn req_status_msg() -> Message {
         let status_req = VTSApiRequest::<i32> {
             data: None,
@@ -567,26 +684,17 @@ impl VTubeStudioPlugin {
         Message::text(token_req_msg)
     }
 
-    fn extract_wave_pingpong_numbers(&self, input: &str) -> HashSet<u64> {
-        let re = Regex::new(r"(Wave|PingPong)(\d+)").unwrap();
-
-        re.captures_iter(input)
-            .filter_map(|caps| caps.get(2)?.as_str().parse::<u64>().ok())
-            .collect()
-    }
-
-    fn precalc_cfg(
-        &self,
-    ) -> (
-        Vec<(String, String, evalexpr::Node)>,
-        HashSet<u64>,
-        VecDeque<Message>,
-    ) {
+    fn load_cfg(&self) -> Result<TransformationConfig, BridgeError> {
         info!(
             "Loadling tranformation config: {}",
             &self.transformation_cfg_path
         );
+        let config = TransformationConfig::load(&self.transformation_cfg_path)?;
+        info!("Tranformation config loaded");
+        Ok(config)
+    }
 
+    fn new_params_msgs(config: &TransformationConfig) -> VecDeque<Message> {
         let def_params = [
             String::from("FacePositionX"),
             String::from("FacePositionY"),
@@ -613,22 +721,17 @@ impl VTubeStudioPlugin {
         ];
 
         let mut new_params: VecDeque<Message> = VecDeque::new();
-        let config = fs::read_to_string(&self.transformation_cfg_path).unwrap();
-        let calc_fns: Vec<CalcFn> = serde_json::from_str(&config[..]).unwrap();
+        for transformation in &config.transformations {
+            let name = &transformation.name;
 
-        let mut timestamps = HashSet::new();
-        let mut precalc_fns: Vec<_> = Vec::new();
-        for func in calc_fns.into_iter() {
-            let name: String = func.name;
-
-            info!("Loading parameter: {}", &name);
-            if !def_params.contains(&name) {
+            info!("Loading parameter: {}", name);
+            if !def_params.contains(name) {
                 let param_data = requests::ParameterCreation {
                     parameter_name: name.clone(),
                     explanation: "Custom Sandoitchi Bridge param".to_string(),
-                    min: func.min,
-                    max: func.max,
-                    default_value: func.default_value,
+                    min: transformation.min,
+                    max: transformation.max,
+                    default_value: transformation.default_value,
                 };
 
                 let param_req = VTSApiRequest {
@@ -643,25 +746,8 @@ impl VTubeStudioPlugin {
 
                 new_params.push_back(Message::text(param_req_msg));
             }
-
-            let local_timestamps = self.extract_wave_pingpong_numbers(&func.func);
-            timestamps = timestamps.union(&local_timestamps).cloned().collect();
-
-            let node = match evalexpr::build_operator_tree(&func.func[..]) {
-                Ok(calc) => calc,
-                Err(error) => {
-                    error!(
-                        "Unable to read cfg (probably error or typo in function): {}",
-                        error
-                    );
-                    panic!()
-                }
-            };
-
-            precalc_fns.push((name, func.func.clone(), node));
         }
 
-        info!("Tranformation config loaded");
-        (precalc_fns, timestamps, new_params)
+        new_params
     }
 }
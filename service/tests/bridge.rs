@@ -0,0 +1,100 @@
+#![cfg(feature = "mock-vts")]
+
+use std::{
+    env, fs,
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use sandoitchi_bridge_service::{
+    bridge::{Bridge, BridgeHandle},
+    tracking::client::TrackingClientType,
+    vts::mock::MockVTubeStudio,
+};
+
+const CONFIG: &str =
+    r#"[{"name": "MouthOpen", "func": "JawOpen", "min": 0, "max": 1, "defaultValue": 0}]"#;
+const IFACIALMOCAP_TCP_PORT: u16 = 49986;
+const VTS_DISCOVERY_PORT: u16 = 47779;
+
+fn join_in_time(handle: BridgeHandle) {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        handle.join();
+        let _ = sender.send(());
+    });
+    receiver
+        .recv_timeout(Duration::from_secs(10))
+        .expect("Bridge threads are not stopped");
+}
+
+// Stands for the phone, which connects to the PC after the handshake
+fn connect_phone() -> TcpStream {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match TcpStream::connect(("127.0.0.1", IFACIALMOCAP_TCP_PORT)) {
+            Ok(stream) => return stream,
+            Err(error) if Instant::now() > deadline => panic!("Unable to connect: {}", error),
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+#[test]
+fn bridge_releases_ports_when_stopped() {
+    let directory = env::temp_dir().join("sandoitchi_bridge_test");
+    fs::create_dir_all(&directory).unwrap();
+    // Plugin keeps the token in the working directory
+    env::set_current_dir(&directory).unwrap();
+    let config = directory.join("config.json");
+    fs::write(&config, CONFIG).unwrap();
+
+    let mock = MockVTubeStudio::start("127.0.0.1:0").unwrap();
+    let bridge = Bridge::builder()
+        .source(TrackingClientType::IFacialMocap, Some("127.0.0.1".into()))
+        .config(config.display().to_string())
+        .vts_endpoint("127.0.0.1".into(), mock.port())
+        .build()
+        .unwrap();
+
+    for _ in 0..3 {
+        let handle = bridge.start().unwrap();
+        let phone = connect_phone();
+        thread::sleep(Duration::from_millis(500));
+
+        handle.stop();
+        join_in_time(handle);
+        drop(phone);
+        TcpListener::bind(("0.0.0.0", IFACIALMOCAP_TCP_PORT))
+            .expect("iFacialMocap port is not released");
+    }
+    assert!(mock.state().authenticated);
+    mock.stop();
+
+    // Without VTubeStudio the plugin keeps searching for it until stopped
+    let free_port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let bridge = Bridge::builder()
+        .source(TrackingClientType::IFacialMocap, Some("127.0.0.1".into()))
+        .config(config.display().to_string())
+        .vts_endpoint("127.0.0.1".into(), free_port)
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        let handle = bridge.start().unwrap();
+        thread::sleep(Duration::from_millis(500));
+
+        handle.stop();
+        join_in_time(handle);
+        UdpSocket::bind(("0.0.0.0", VTS_DISCOVERY_PORT))
+            .expect("VTubeStudio discovery port is not released");
+        TcpListener::bind(("0.0.0.0", IFACIALMOCAP_TCP_PORT))
+            .expect("iFacialMocap port is not released");
+    }
+}
@@ -0,0 +1,233 @@
+#![cfg(feature = "mock-vts")]
+
+use std::{
+    env, fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use sandoitchi_bridge_service::{
+    tracking::response::{Cords, Shape, TrackingResponse},
+    vts::{
+        mock::{MockState, MockVTubeStudio},
+        plugin::VTubeStudioPlugin,
+    },
+};
+
+// Default parameters of VTubeStudio are not created
+const CONFIG: &str = r#"[
+    {"name": "MouthOpen", "func": "JawOpen", "min": 0, "max": 1, "defaultValue": 0},
+    {"name": "BridgeJaw", "func": "JawOpen * 2", "min": 0, "max": 1, "defaultValue": 0}
+]"#;
+const JAW_OPEN: f64 = 0.5;
+
+// Plugin keeps the token in the working directory, which is shared by the tests
+static WORKING_DIRECTORY: Mutex<()> = Mutex::new(());
+
+struct RunningPlugin {
+    active: Arc<AtomicBool>,
+    handles: Vec<JoinHandle<()>>,
+    _directory: MutexGuard<'static, ()>,
+}
+
+impl RunningPlugin {
+    fn start(mock: &MockVTubeStudio, token: Option<&str>) -> Self {
+        let directory_lock = WORKING_DIRECTORY
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let directory = env::temp_dir().join("sandoitchi_plugin_test");
+        fs::create_dir_all(&directory).unwrap();
+        env::set_current_dir(&directory).unwrap();
+        match token {
+            Some(token) => fs::write("token", token).unwrap(),
+            None => {
+                let _ = fs::remove_file("token");
+            }
+        }
+        let config = directory.join("config.json");
+        fs::write(&config, CONFIG).unwrap();
+
+        let active = Arc::new(AtomicBool::new(true));
+        let (sender, receiver) = mpsc::channel();
+        let plugin = VTubeStudioPlugin::new(
+            vec![("phone".into(), receiver)],
+            config.display().to_string(),
+            0,
+            3000,
+            0,
+        )
+        .with_vts_endpoint("127.0.0.1".into(), mock.port());
+
+        let tracking = {
+            let active = Arc::clone(&active);
+            thread::spawn(move || {
+                while active.load(Ordering::Relaxed) && sender.send(frame()).is_ok() {
+                    thread::sleep(Duration::from_millis(20));
+                }
+            })
+        };
+        let plugin = {
+            let active = Arc::clone(&active);
+            thread::spawn(move || plugin.run(active))
+        };
+
+        Self {
+            active,
+            handles: vec![tracking, plugin],
+            _directory: directory_lock,
+        }
+    }
+
+    fn stop(self) {
+        self.active.store(false, Ordering::Relaxed);
+        for handle in self.handles {
+            handle.join().unwrap();
+        }
+    }
+}
+
+fn frame() -> TrackingResponse {
+    TrackingResponse {
+        timestamp: 0,
+        hotkey: 0,
+        face_found: true,
+        rotation: Cords::default(),
+        position: Cords::default(),
+        eye_left: Cords::default(),
+        blend_shapes: vec![Shape {
+            k: "JawOpen".into(),
+            v: JAW_OPEN,
+        }],
+    }
+}
+
+fn wait_for(mock: &MockVTubeStudio, condition: impl Fn(&MockState) -> bool) -> MockState {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let state = mock.state();
+        if condition(&state) {
+            return state;
+        }
+        assert!(Instant::now() < deadline, "Mock state: {:?}", state);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn count(state: &MockState, message_type: &str) -> usize {
+    state
+        .requests
+        .iter()
+        .filter(|request| *request == message_type)
+        .count()
+}
+
+fn injecting(state: &MockState) -> bool {
+    state.injection_count >= 3
+}
+
+#[test]
+fn plugin_requests_token_creates_parameters_and_injects() {
+    let mock = MockVTubeStudio::start("127.0.0.1:0").unwrap();
+    let plugin = RunningPlugin::start(&mock, None);
+
+    let state = wait_for(&mock, injecting);
+    let token = fs::read_to_string("token").unwrap();
+    plugin.stop();
+
+    assert_eq!(token, MockVTubeStudio::TOKEN);
+    assert_eq!(
+        state.requests[..4],
+        [
+            "APIStateRequest",
+            "AuthenticationTokenRequest",
+            "AuthenticationRequest",
+            "ParameterCreationRequest",
+        ]
+    );
+    assert!(state.authenticated);
+    assert_eq!(state.created_parameters, ["BridgeJaw"]);
+    assert_eq!(state.parameter_values["MouthOpen"], JAW_OPEN);
+    assert_eq!(state.parameter_values["BridgeJaw"], JAW_OPEN * 2.0);
+    assert_eq!(state.last_face_found, Some(true));
+}
+
+#[test]
+fn plugin_reconnects_and_authenticates_with_saved_token() {
+    let mock = MockVTubeStudio::start("127.0.0.1:0").unwrap();
+    let port = mock.port();
+    let plugin = RunningPlugin::start(&mock, Some(MockVTubeStudio::TOKEN));
+    wait_for(&mock, injecting);
+    mock.stop();
+
+    // VTubeStudio restarted on the same port
+    let mock = MockVTubeStudio::start(&format!("127.0.0.1:{}", port)).unwrap();
+    let state = wait_for(&mock, injecting);
+    plugin.stop();
+
+    assert!(state.authenticated);
+    assert_eq!(count(&state, "AuthenticationTokenRequest"), 0);
+    assert_eq!(count(&state, "AuthenticationRequest"), 1);
+    assert_eq!(state.created_parameters, ["BridgeJaw"]);
+}
+
+#[test]
+fn plugin_authenticates_again_when_authentication_is_required() {
+    let mock = MockVTubeStudio::start("127.0.0.1:0").unwrap();
+    mock.fail_next("ParameterCreationRequest", 8);
+    mock.fail_next("InjectParameterDataRequest", 8);
+    let plugin = RunningPlugin::start(&mock, Some(MockVTubeStudio::TOKEN));
+
+    let state = wait_for(&mock, injecting);
+    plugin.stop();
+
+    // Once after the status and once after every failed request
+    assert_eq!(count(&state, "AuthenticationRequest"), 3);
+    assert_eq!(count(&state, "ParameterCreationRequest"), 2);
+    assert_eq!(state.created_parameters, ["BridgeJaw"]);
+}
+
+#[test]
+fn plugin_requests_token_again_when_denied() {
+    let mock = MockVTubeStudio::start("127.0.0.1:0").unwrap();
+    mock.fail_next("AuthenticationTokenRequest", 50);
+    let plugin = RunningPlugin::start(&mock, None);
+
+    let state = wait_for(&mock, injecting);
+    plugin.stop();
+
+    assert_eq!(count(&state, "AuthenticationTokenRequest"), 2);
+    assert!(state.authenticated);
+}
+
+#[test]
+fn plugin_skips_parameters_that_can_not_be_created() {
+    for error_id in [352, 354] {
+        let mock = MockVTubeStudio::start("127.0.0.1:0").unwrap();
+        mock.fail_next("ParameterCreationRequest", error_id);
+        let plugin = RunningPlugin::start(&mock, Some(MockVTubeStudio::TOKEN));
+
+        let state = wait_for(&mock, injecting);
+        plugin.stop();
+
+        assert_eq!(count(&state, "ParameterCreationRequest"), 1);
+        assert!(state.created_parameters.is_empty());
+        assert_eq!(state.parameter_values["BridgeJaw"], JAW_OPEN * 2.0);
+    }
+}
+
+#[test]
+fn plugin_keeps_injecting_after_rejected_injection() {
+    let mock = MockVTubeStudio::start("127.0.0.1:0").unwrap();
+    mock.fail_next("InjectParameterDataRequest", 450);
+    let plugin = RunningPlugin::start(&mock, Some(MockVTubeStudio::TOKEN));
+
+    let state = wait_for(&mock, injecting);
+    plugin.stop();
+
+    assert!(count(&state, "InjectParameterDataRequest") > state.injection_count);
+    assert_eq!(count(&state, "AuthenticationRequest"), 1);
+}
@@ -0,0 +1,394 @@
+// Impersonates a phone with a tracking app, so tracking clients can be tested on localhost
+use std::{
+    io::Write,
+    net::{IpAddr, SocketAddr, TcpStream, UdpSocket},
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use sandoitchi_bridge_service::{
+    recording::RecordReader,
+    tracking::{
+        arkit::BLEND_SHAPES,
+        response::{Cords, Shape, TrackingResponse},
+    },
+    utils::get_current_timestamp,
+};
+use serde_json::Value;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Protocol {
+    VTubeStudio,
+    IFacialMocap,
+}
+
+fn parse_protocol(input: &str) -> Result<Protocol, String> {
+    match input.to_lowercase().as_str() {
+        "vts" | "vtubestudio" => Ok(Protocol::VTubeStudio),
+        "ifm" | "ifacialmocap" => Ok(Protocol::IFacialMocap),
+        _ => Err(format!("Invalid tracking client type: {}", input)),
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Phone simulator for testing tracking clients", long_about = None)]
+struct Args {
+    #[arg(
+        short,
+        long,
+        value_parser = parse_protocol,
+        help = "Tracking application to impersonate: vts or ifm"
+    )]
+    tracking_client: Protocol,
+    #[arg(
+        short,
+        long,
+        help = "Recording to send in a loop. Default: synthetic face"
+    )]
+    input: Option<String>,
+    #[arg(
+        long,
+        default_value_t = 60,
+        hide_default_value = true,
+        help = "Frames per second. Default: 60"
+    )]
+    fps: u32,
+    #[arg(
+        long,
+        default_value_t = 49983,
+        hide_default_value = true,
+        help = "UDP port for the iFacialMocap handshake. The bridge takes 49983 in UDP mode, so on the same PC use another one with --ifm-phone-port. Default: 49983"
+    )]
+    ifm_port: u16,
+}
+
+enum FrameSource {
+    Synthetic(Instant),
+    // Frames of the first recorded source
+    Recording {
+        path: String,
+        reader: RecordReader,
+        source: Option<String>,
+    },
+}
+
+impl FrameSource {
+    fn open(input: Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        match input {
+            Some(path) => Ok(FrameSource::Recording {
+                reader: RecordReader::open(&path)?,
+                path,
+                source: None,
+            }),
+            None => Ok(FrameSource::Synthetic(Instant::now())),
+        }
+    }
+
+    fn next(&mut self) -> Result<TrackingResponse, Box<dyn std::error::Error>> {
+        match self {
+            FrameSource::Synthetic(start) => Ok(synthetic_frame(start.elapsed().as_secs_f64())),
+            FrameSource::Recording {
+                path,
+                reader,
+                source,
+            } => {
+                let mut reopened = false;
+                loop {
+                    let Some(record) = reader.read()? else {
+                        if reopened {
+                            return Err("Recording is empty".into());
+                        }
+                        *reader = RecordReader::open(path)?;
+                        reopened = true;
+                        continue;
+                    };
+                    if *source.get_or_insert_with(|| record.source.clone()) == record.source {
+                        return Ok(record.response);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Head swaying, talking and blinking every 4 seconds
+fn synthetic_frame(time: f64) -> TrackingResponse {
+    let blink = if time % 4.0 < 0.15 { 1.0 } else { 0.0 };
+    let smile = ((time * 0.4).sin() + 1.0) / 4.0;
+
+    let blend_shapes = BLEND_SHAPES
+        .iter()
+        .map(|name| Shape {
+            k: name.to_string(),
+            v: match *name {
+                "JawOpen" => ((time * 2.0).sin() + 1.0) / 2.0,
+                "EyeBlinkLeft" | "EyeBlinkRight" => blink,
+                "MouthSmileLeft" | "MouthSmileRight" => smile,
+                _ => 0.0,
+            },
+        })
+        .collect();
+
+    TrackingResponse {
+        timestamp: get_current_timestamp(),
+        hotkey: 0,
+        face_found: true,
+        rotation: Cords {
+            x: 10.0 * (time * 0.7).sin(),
+            y: 20.0 * (time * 0.5).sin(),
+            z: 5.0 * (time * 0.3).sin(),
+        },
+        position: Cords {
+            x: (time * 0.5).sin(),
+            y: (time * 0.4).sin(),
+            z: 0.0,
+        },
+        eye_left: Cords {
+            x: 5.0 * time.sin(),
+            y: 5.0 * (time * 0.8).sin(),
+            z: 0.0,
+        },
+        blend_shapes,
+    }
+}
+
+// "EyeBlinkLeft" -> "eyeBlink_L"
+fn ifacialmocap_shape_name(name: &str) -> String {
+    let name = if let Some(base) = name.strip_suffix("Left") {
+        format!("{base}_L")
+    } else if let Some(base) = name.strip_suffix("Right") {
+        format!("{base}_R")
+    } else {
+        name.to_string()
+    };
+    let mut characters = name.chars();
+    match characters.next() {
+        None => String::new(),
+        Some(first) => first.to_lowercase().collect::<String>() + characters.as_str(),
+    }
+}
+
+// Only ARKit blendshapes in 0...100, head is "rotY,rotX,rotZ,posX,posY,posZ"
+fn ifacialmocap_values(frame: &TrackingResponse) -> (Vec<(String, i64)>, [f64; 6], [f64; 3]) {
+    let shapes = frame
+        .blend_shapes
+        .iter()
+        .filter(|shape| BLEND_SHAPES.contains(&shape.k.as_str()))
+        .map(|shape| {
+            (
+                ifacialmocap_shape_name(&shape.k),
+                (shape.v * 100.0).round() as i64,
+            )
+        })
+        .collect();
+    let head = [
+        frame.rotation.y,
+        frame.rotation.x,
+        frame.rotation.z,
+        frame.position.x,
+        frame.position.y,
+        frame.position.z,
+    ];
+    let eye = [frame.eye_left.x, frame.eye_left.y, frame.eye_left.z];
+    (shapes, head, eye)
+}
+
+fn ifacialmocap_text(frame: &TrackingResponse) -> String {
+    let (shapes, head, eye) = ifacialmocap_values(frame);
+    let join = |values: &[f64]| {
+        values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    let mut text = format!("___iFacialMocaptrackingStatus-{}|", frame.face_found as u8);
+    for (name, value) in shapes {
+        text.push_str(&format!("{name}-{value}|"));
+    }
+    text.push_str(&format!(
+        "=head#{}|rightEye#{}|leftEye#{}|",
+        join(&head),
+        join(&eye),
+        join(&eye)
+    ));
+    text
+}
+
+fn ifacialmocap_json(frame: &TrackingResponse) -> String {
+    let (shapes, head, eye) = ifacialmocap_values(frame);
+    let blend_shapes: serde_json::Map<String, Value> = shapes
+        .into_iter()
+        .map(|(name, value)| (name, value.into()))
+        .collect();
+
+    serde_json::json!({
+        "blendShapes": blend_shapes,
+        "head": head,
+        "rightEye": eye,
+        "leftEye": eye,
+        "trackingStatus": frame.face_found as u8,
+    })
+    .to_string()
+}
+
+fn wait_for_next_frame(next_frame: &mut Instant, interval: Duration) {
+    let now = Instant::now();
+    if *next_frame > now {
+        thread::sleep(*next_frame - now);
+    }
+    *next_frame = (*next_frame + interval).max(now);
+}
+
+// Answers "iOSTrackingDataRequest" and streams frames to the requested ports while requests keep coming
+fn serve_vtubestudio(
+    frames: &mut FrameSource,
+    interval: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    const PHONE_PORT: u16 = 21412;
+
+    let socket = UdpSocket::bind(("0.0.0.0", PHONE_PORT))?;
+    socket.set_nonblocking(true)?;
+    println!("Waiting for VTubeStudio tracking requests on port {PHONE_PORT}");
+
+    let mut buf = [0; 4096];
+    let mut clients: Vec<(SocketAddr, Instant)> = Vec::new();
+    let mut next_frame = Instant::now();
+
+    loop {
+        while let Ok((amt, src)) = socket.recv_from(&mut buf) {
+            let Ok(request) = serde_json::from_slice::<Value>(&buf[..amt]) else {
+                continue;
+            };
+            if request["messageType"] != "iOSTrackingDataRequest" {
+                continue;
+            }
+            let until = Instant::now()
+                + Duration::from_secs(request["sendForSeconds"].as_u64().unwrap_or(10));
+            let ports = request["ports"].as_array().cloned().unwrap_or_default();
+            for port in ports.iter().filter_map(Value::as_u64) {
+                let address = SocketAddr::new(src.ip(), port as u16);
+                match clients.iter_mut().find(|(client, _)| *client == address) {
+                    Some((_, client_until)) => *client_until = until,
+                    None => {
+                        println!("Streaming to {address}");
+                        clients.push((address, until));
+                    }
+                }
+            }
+        }
+
+        clients.retain(|(address, until)| {
+            let requested = *until > Instant::now();
+            if !requested {
+                println!("Stopped streaming to {address}");
+            }
+            requested
+        });
+
+        if !clients.is_empty() {
+            let frame = serde_json::to_string(&frames.next()?)?;
+            for (address, _) in &clients {
+                let _ = socket.send_to(frame.as_bytes(), address);
+            }
+        }
+
+        wait_for_next_frame(&mut next_frame, interval);
+    }
+}
+
+// Answers the handshake by connecting to the TCP port of the PC or by streaming UDP packets back
+fn serve_ifacialmocap(
+    frames: &mut FrameSource,
+    interval: Duration,
+    port: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    const TCP_PORT: u16 = 49986;
+    // PC starts its TCP server after sending the handshake
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    let socket = UdpSocket::bind(("0.0.0.0", port))?;
+    socket.set_nonblocking(true)?;
+    println!("Waiting for iFacialMocap handshake on port {port}");
+
+    let mut buf = [0; 4096];
+    let mut json = false;
+    let mut pending_tcp: Option<(IpAddr, Instant)> = None;
+    let mut tcp: Option<TcpStream> = None;
+    let mut udp: Option<SocketAddr> = None;
+    let mut next_frame = Instant::now();
+
+    loop {
+        while let Ok((amt, src)) = socket.recv_from(&mut buf) {
+            let Ok(message) = std::str::from_utf8(&buf[..amt]) else {
+                continue;
+            };
+            if !message.starts_with("iFacialMocap_") {
+                continue;
+            }
+            json = message.ends_with("|json");
+            if message.starts_with("iFacialMocap_UDPTCP_") {
+                pending_tcp = Some((src.ip(), Instant::now() + CONNECT_TIMEOUT));
+                udp = None;
+            } else {
+                if udp != Some(src) {
+                    println!("Streaming UDP to {src}");
+                }
+                udp = Some(src);
+                tcp = None;
+            }
+        }
+
+        if let Some((ip, deadline)) = pending_tcp {
+            if let Ok(stream) = TcpStream::connect((ip, TCP_PORT)) {
+                println!("Streaming TCP to {ip}:{TCP_PORT}");
+                tcp = Some(stream);
+                pending_tcp = None;
+            } else if Instant::now() > deadline {
+                println!("Unable to connect to {ip}:{TCP_PORT}");
+                pending_tcp = None;
+            }
+        }
+
+        if tcp.is_some() || udp.is_some() {
+            let frame = frames.next()?;
+            let data = if json {
+                ifacialmocap_json(&frame)
+            } else {
+                ifacialmocap_text(&frame)
+            };
+            if let Some(stream) = &mut tcp {
+                if stream.write_all(data.as_bytes()).is_err() {
+                    println!("TCP connection closed");
+                    tcp = None;
+                }
+            }
+            if let Some(address) = udp {
+                let _ = socket.send_to(data.as_bytes(), address);
+            }
+        }
+
+        wait_for_next_frame(&mut next_frame, interval);
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut frames = FrameSource::open(args.input).unwrap_or_else(|error| {
+        eprintln!("Unable to open recording: {}", error);
+        process::exit(1);
+    });
+    let interval = Duration::from_secs_f64(1.0 / args.fps.max(1) as f64);
+
+    let result = match args.tracking_client {
+        Protocol::VTubeStudio => serve_vtubestudio(&mut frames, interval),
+        Protocol::IFacialMocap => serve_ifacialmocap(&mut frames, interval, args.ifm_port),
+    };
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
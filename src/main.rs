@@ -1,45 +1,275 @@
 use std::{
-    sync::{
-        atomic::AtomicBool,
-        mpsc::{self, Receiver, Sender},
-        Arc,
-    },
-    thread,
+    fs::File,
+    io::{self, BufWriter, Write},
+    process,
+    time::Duration,
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use sandoitchi_bridge_service::{
+    bridge::Bridge,
+    calibration::Calibration,
+    check::{check_config, Severity},
+    recording::{RecordReader, RecordingFormat},
     tracking::{
-        client::{TrackingClient, TrackingClientType},
-        ifacialmocap::IFacialMocapTrackingClinet,
-        response::TrackingResponse,
-        vtubestudio::VTubeStudioTrackingClient,
+        client::{TrackingClientType, TrackingOptions},
+        ifacialmocap::{IFacialMocapFormat, IFacialMocapTransport},
     },
-    vts::plugin::VTubeStudioPlugin,
+    transform::{config_schema, evaluate_recording, TransformationConfig},
 };
 
 fn parse_tracking_client_type(input: &str) -> Result<TrackingClientType, String> {
     match input.to_lowercase().as_str() {
         "vts" | "vtubestudio" => Ok(TrackingClientType::VTubeStudio),
         "ifm" | "ifacialmocap" => Ok(TrackingClientType::IFacialMocap),
+        "vmc" => Ok(TrackingClientType::Vmc),
+        "osf" | "openseeface" => Ok(TrackingClientType::OpenSeeFace),
+        "fm3d" | "facemotion3d" => Ok(TrackingClientType::Facemotion3d),
+        "meow" | "meowface" => Ok(TrackingClientType::MeowFace),
+        "llf" | "livelinkface" => Ok(TrackingClientType::LiveLinkFace),
+        "replay" => Ok(TrackingClientType::Replay),
         _ => Err(format!("Invalid tracking client type: {}", input)),
     }
 }
 
+fn parse_ifm_transport(input: &str) -> Result<IFacialMocapTransport, String> {
+    match input.to_lowercase().as_str() {
+        "tcp" => Ok(IFacialMocapTransport::Tcp),
+        "udp" => Ok(IFacialMocapTransport::Udp),
+        _ => Err(format!("Invalid iFacialMocap transport: {}", input)),
+    }
+}
+
+fn parse_ifm_format(input: &str) -> Result<IFacialMocapFormat, String> {
+    match input.to_lowercase().as_str() {
+        "text" => Ok(IFacialMocapFormat::Text),
+        "json" => Ok(IFacialMocapFormat::Json),
+        _ => Err(format!("Invalid iFacialMocap format: {}", input)),
+    }
+}
+
+fn parse_recording_format(input: &str) -> Result<RecordingFormat, String> {
+    match input.to_lowercase().as_str() {
+        "jsonl" | "json" => Ok(RecordingFormat::JsonLines),
+        "bin" | "binary" => Ok(RecordingFormat::Binary),
+        _ => Err(format!("Invalid recording format: {}", input)),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TrackingSourceArg {
+    client_type: TrackingClientType,
+    ip: Option<String>,
+}
+
+// "ifm" or "ifm@192.168.0.174"
+fn parse_tracking_source(input: &str) -> Result<TrackingSourceArg, String> {
+    let (client_type, ip) = match input.split_once('@') {
+        Some((client_type, ip)) => (client_type, Some(ip.to_string())),
+        None => (input, None),
+    };
+    Ok(TrackingSourceArg {
+        client_type: parse_tracking_client_type(client_type)?,
+        ip,
+    })
+}
+
+// Client specific settings of run and calibrate
+#[derive(clap::Args, Debug)]
+struct TrackingOptionArgs {
+    #[arg(
+        long,
+        value_parser = parse_ifm_transport,
+        default_value = "tcp",
+        hide_default_value = true,
+        help = "iFacialMocap transport: tcp or udp. Default: tcp"
+    )]
+    ifm_transport: IFacialMocapTransport,
+    #[arg(
+        long,
+        value_parser = parse_ifm_format,
+        default_value = "text",
+        hide_default_value = true,
+        help = "iFacialMocap data format: text or json. Default: text"
+    )]
+    ifm_format: IFacialMocapFormat,
+    #[arg(
+        long,
+        default_value_t = 49983,
+        hide_default_value = true,
+        help = "UDP port of the iFacialMocap app, another one for the phone simulator on the same PC. Default: 49983"
+    )]
+    ifm_phone_port: u16,
+    #[arg(
+        long,
+        default_value_t = 0,
+        hide_default_value = true,
+        help = "Fixed UDP port for VTubeStudio and MeowFace tracking data. Default: 0 (any free port)"
+    )]
+    vts_listen_port: u16,
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        help = "Recording to play with the replay client"
+    )]
+    replay_file: String,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        hide_default_value = true,
+        help = "Replay speed multiplier. Default: 1"
+    )]
+    replay_speed: f64,
+    #[arg(long, help = "Start the replay over when the recording ends")]
+    replay_loop: bool,
+    #[arg(
+        long,
+        default_value_t = 0,
+        hide_default_value = true,
+        help = "Milliseconds from the beginning of the recording to start the replay at. Default: 0"
+    )]
+    replay_start: u64,
+    #[arg(
+        long,
+        default_value = "",
+        hide_default_value = true,
+        help = "Name of the recorded source to replay (ifm, vmc2, ...). Default: the first one in the recording"
+    )]
+    replay_source: String,
+}
+
+impl From<TrackingOptionArgs> for TrackingOptions {
+    fn from(args: TrackingOptionArgs) -> Self {
+        Self {
+            ifm_transport: args.ifm_transport,
+            ifm_format: args.ifm_format,
+            ifm_phone_port: args.ifm_phone_port,
+            vts_listen_port: args.vts_listen_port,
+            replay_file: args.replay_file,
+            replay_speed: args.replay_speed,
+            replay_loop: args.replay_loop,
+            replay_start: args.replay_start,
+            replay_source: args.replay_source,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Calculate parameters for every frame of a recording without VTubeStudio")]
+    Eval(EvalArgs),
+    #[command(
+        about = "Check a config for errors and typos in variable names without starting the bridge"
+    )]
+    Check(CheckArgs),
+    #[command(about = "Print JSON Schema of JSON and YAML configs")]
+    Schema,
+    #[command(about = "Record the neutral face to subtract it in X_cal variables")]
+    Calibrate(CalibrateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct EvalArgs {
+    #[arg(
+        short,
+        long,
+        help = "Path to JSON, YAML or TOML config with transformations"
+    )]
+    config: String,
+    #[arg(short, long, help = "Recording made with --record")]
+    input: String,
+    #[arg(
+        short,
+        long,
+        help = "Path to CSV with parameter values. Default: standard output"
+    )]
+    output: Option<String>,
+    #[arg(long, help = "Calibration made with the calibrate command")]
+    calibration: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    #[arg(
+        short,
+        long,
+        help = "Path to JSON, YAML or TOML config with transformations"
+    )]
+    config: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct CalibrateArgs {
+    #[arg(
+        short,
+        long,
+        value_parser = parse_tracking_source,
+        help = "Tracking application type, optionally with its phone IP (ifm@192.168.0.174)"
+    )]
+    tracking_client: TrackingSourceArg,
+    #[arg(
+        short,
+        long,
+        default_value = "",
+        hide_default_value = true,
+        help = "Phone IP address. VTubeStudio and MeowFace search for the phone when it's not set"
+    )]
+    phone_ip: String,
+    #[arg(
+        short,
+        long,
+        default_value_t = 5,
+        hide_default_value = true,
+        help = "Seconds to record the neutral face for. Default: 5"
+    )]
+    seconds: u64,
+    #[arg(
+        short,
+        long,
+        default_value = "calibration.json",
+        hide_default_value = true,
+        help = "Path to save the calibration to. Default: calibration.json"
+    )]
+    output: String,
+    #[command(flatten)]
+    tracking_options: TrackingOptionArgs,
+}
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    #[arg(short, long, help = "Path to JSON config with transformations")]
-    config: String,
-    #[arg(short, long, help = "Phone IP address")]
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(
+        short,
+        long,
+        required = true,
+        help = "Path to JSON, YAML or TOML config with transformations"
+    )]
+    config: Option<String>,
+    #[arg(
+        short,
+        long,
+        default_value = "",
+        hide_default_value = true,
+        help = "Phone IP address. VTubeStudio and MeowFace search for the phone when it's not set"
+    )]
     phone_ip: String,
     #[arg(
         short,
         long,
-        value_parser = parse_tracking_client_type,
-        help = "Tracking application type"
+        required = true,
+        value_parser = parse_tracking_source,
+        help = "Tracking application type, optionally with its own phone IP (ifm@192.168.0.174). Can be repeated, the first one is primary"
     )]
-    tracking_client: TrackingClientType,
+    tracking_client: Vec<TrackingSourceArg>,
     #[arg(
         short,
         long,
@@ -48,6 +278,27 @@ struct Args {
         help = "The time in milliseconds to wait before changing FaceFound to 0. Default: 3000"
     )]
     face_search_timeout: u64,
+    #[arg(
+        long,
+        default_value_t = 1000,
+        hide_default_value = true,
+        help = "The time in milliseconds without data from a tracking client before switching to the next one. Default: 1000, 0 disables switching"
+    )]
+    failover_timeout: u64,
+    #[arg(
+        long,
+        default_value = "localhost",
+        hide_default_value = true,
+        help = "Host of VTubeStudio API. Default: localhost"
+    )]
+    vts_host: String,
+    #[arg(
+        long,
+        default_value_t = 8001,
+        hide_default_value = true,
+        help = "Port of VTubeStudio API, it's discovered when VTubeStudio is not found there. Default: 8001"
+    )]
+    vts_port: u16,
     #[arg(
         short = 'd',
         long,
@@ -56,39 +307,152 @@ struct Args {
         help = "Optional delay for config reloading in milliseconds. Default: 0 (disabled)"
     )]
     config_reload_delay: u64,
+    #[arg(
+        long,
+        help = "Calibration made with the calibrate command. Default: X_cal variables equal X"
+    )]
+    calibration: Option<String>,
+    #[arg(
+        long,
+        help = "Directory to record received tracking data to. Default: recording is disabled"
+    )]
+    record: Option<String>,
+    #[arg(
+        long,
+        value_parser = parse_recording_format,
+        default_value = "jsonl",
+        hide_default_value = true,
+        help = "Recording format: jsonl or binary. Default: jsonl"
+    )]
+    record_format: RecordingFormat,
+    #[command(flatten)]
+    tracking_options: TrackingOptionArgs,
+}
+
+fn evaluate(args: EvalArgs) -> Result<usize, Box<dyn std::error::Error>> {
+    let config = TransformationConfig::load(&args.config)?;
+    let calibration = match args.calibration {
+        Some(path) => Calibration::load(&path)?,
+        None => Calibration::default(),
+    };
+    let mut reader = RecordReader::open(&args.input)?;
+    let mut output: Box<dyn Write> = match args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let rows = evaluate_recording(&config, &calibration, &mut reader, &mut output)?;
+    output.flush()?;
+    Ok(rows)
+}
+
+// Exit code is 1 when the config has errors, warnings alone don't fail the check
+fn check(args: CheckArgs) -> i32 {
+    let issues = match check_config(&args.config) {
+        Ok(issues) => issues,
+        Err(error) => {
+            println!("error: {}", error);
+            return 1;
+        }
+    };
+
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    println!(
+        "{}: {} errors, {} warnings",
+        args.config,
+        errors,
+        issues.len() - errors
+    );
+
+    if errors > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn calibrate(args: CalibrateArgs) -> Result<usize, Box<dyn std::error::Error>> {
+    println!(
+        "Keep a neutral face for {} seconds after the face is found",
+        args.seconds
+    );
+    let calibration = Calibration::record(
+        args.tracking_client.client_type,
+        args.tracking_client.ip.unwrap_or(args.phone_ip),
+        args.tracking_options.into(),
+        Duration::from_secs(args.seconds),
+    )?;
+    calibration.save(&args.output)?;
+    Ok(calibration.variables.len())
 }
 
 fn main() {
     let args = Args::parse();
 
-    println!("Github: https://github.com/an1by/SandoitchiBridge");
+    match args.command {
+        Some(Command::Eval(eval_args)) => {
+            match evaluate(eval_args) {
+                Ok(rows) => eprintln!("Evaluated {} frames", rows),
+                Err(error) => {
+                    eprintln!("Unable to evaluate: {}", error);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Check(check_args)) => process::exit(check(check_args)),
+        Some(Command::Schema) => {
+            println!("{}", config_schema());
+            return;
+        }
+        Some(Command::Calibrate(calibrate_args)) => {
+            let output = calibrate_args.output.clone();
+            match calibrate(calibrate_args) {
+                Ok(variables) => println!("Saved {} variables to {}", variables, output),
+                Err(error) => {
+                    eprintln!("Unable to calibrate: {}", error);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        None => {}
+    }
 
-    let active_flag = Arc::new(AtomicBool::new(true));
-    let active_flag_clone = Arc::clone(&active_flag);
+    println!("Github: https://github.com/an1by/SandoitchiBridge");
 
     let log_config = include_str!("../configs/log_cfg.yml");
     let raw_log_config = serde_yaml::from_str(log_config).unwrap();
     log4rs::init_raw_config(raw_log_config).unwrap();
 
-    let (sender, receiver): (Sender<TrackingResponse>, Receiver<TrackingResponse>) =
-        mpsc::channel();
-
-    let pctr_handler = thread::spawn(move || {
-        VTubeStudioPlugin::new(
-            receiver,
-            args.config,
-            args.config_reload_delay,
-            args.face_search_timeout,
-        ).run(active_flag);
-    });
-
-    let function: fn(ip: String, sender: Sender<TrackingResponse>, active: Arc<AtomicBool>);
-    match args.tracking_client {
-        TrackingClientType::VTubeStudio => function = VTubeStudioTrackingClient::run,
-        TrackingClientType::IFacialMocap => function = IFacialMocapTrackingClinet::run,
+    let mut builder = Bridge::builder()
+        .config(args.config.unwrap())
+        .phone_ip(args.phone_ip)
+        .options(args.tracking_options.into())
+        .config_reload_delay(args.config_reload_delay)
+        .face_search_timeout(args.face_search_timeout)
+        .failover_timeout(args.failover_timeout)
+        .vts_endpoint(args.vts_host, args.vts_port);
+    for source in args.tracking_client {
+        builder = builder.source(source.client_type, source.ip);
+    }
+    if let Some(path) = args.calibration {
+        builder = builder.calibration(path);
+    }
+    if let Some(directory) = args.record {
+        builder = builder.record(directory, args.record_format);
     }
-    let phonetr_handler = thread::spawn(move || function(args.phone_ip, sender, active_flag_clone));
 
-    let _ = pctr_handler.join();
-    let _ = phonetr_handler.join();
+    match builder.build().and_then(|bridge| bridge.start()) {
+        Ok(handle) => handle.join(),
+        Err(error) => {
+            eprintln!("Unable to start: {}", error);
+            process::exit(1);
+        }
+    }
 }
@@ -2,26 +2,13 @@
 extern crate native_windows_derive as nwd;
 extern crate native_windows_gui as nwg;
 
-use std::{
-    env, fs,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc::{self, Receiver, Sender},
-        Arc,
-    },
-    thread::{self},
-};
+use std::{cell::RefCell, env, fs};
 
 use nwd::NwgUi;
 use nwg::{NativeUi, NumberSelectData};
 use sandoitchi_bridge_service::{
-    tracking::{
-        client::{TrackingClient, TrackingClientType},
-        ifacialmocap::IFacialMocapTrackingClinet,
-        response::TrackingResponse,
-        vtubestudio::VTubeStudioTrackingClient,
-    },
-    vts::plugin::VTubeStudioPlugin,
+    bridge::{Bridge, BridgeHandle},
+    tracking::client::TrackingClientType,
 };
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -33,9 +20,15 @@ pub struct UIConfig {
     face_search_timeout: Option<i64>,
 }
 
-const TRACKING_CLIENT_TYPES: [TrackingClientType; 2] = [
+// Replay needs a recording file, so it's available only in CLI
+const TRACKING_CLIENT_TYPES: [TrackingClientType; 7] = [
     TrackingClientType::VTubeStudio,
     TrackingClientType::IFacialMocap,
+    TrackingClientType::Vmc,
+    TrackingClientType::OpenSeeFace,
+    TrackingClientType::Facemotion3d,
+    TrackingClientType::MeowFace,
+    TrackingClientType::LiveLinkFace,
 ];
 
 #[derive(Default, NwgUi)]
@@ -91,7 +84,7 @@ pub struct App {
     #[nwg_events(OnMenuItemSelected: [App::exit])]
     tray_exit: nwg::MenuItem,
 
-    active: Arc<AtomicBool>,
+    bridge: RefCell<Option<BridgeHandle>>,
 }
 
 impl App {
@@ -155,8 +148,13 @@ impl App {
     }
 
     fn connect(&self) {
-        if !self.active.load(Ordering::Relaxed) {
-            self.active.store(true, Ordering::Relaxed);
+        let mut bridge = self.bridge.borrow_mut();
+        if !bridge.as_ref().is_some_and(BridgeHandle::is_running) {
+            // Ports of the previous connection have to be released before binding them again
+            if let Some(previous) = bridge.take() {
+                previous.join();
+            }
+
             let path = self.transform_file_path.text().clone();
             let ip = self.phone_ip.text().clone();
             let face_search_timeout: i64 = self
@@ -166,35 +164,33 @@ impl App {
                 .parse::<i64>()
                 .unwrap();
 
-            let (sender, receiver): (Sender<TrackingResponse>, Receiver<TrackingResponse>) =
-                mpsc::channel();
-
-            let flag_pc = Arc::clone(&self.active);
-            let flag_ph = Arc::clone(&self.active);
-
-            let _ = thread::spawn(move || {
-                VTubeStudioPlugin::new(receiver, path, 0, face_search_timeout.unsigned_abs())
-                    .run(flag_pc);
-            });
-
-            let function: fn(
-                ip: String,
-                sender: Sender<TrackingResponse>,
-                active: Arc<AtomicBool>,
-            );
             let tracking_index = self.tracking_client_type.selection().unwrap();
-            match TRACKING_CLIENT_TYPES.get(tracking_index).unwrap() {
-                TrackingClientType::VTubeStudio => function = VTubeStudioTrackingClient::run,
-                TrackingClientType::IFacialMocap => function = IFacialMocapTrackingClinet::run,
+            let tracking_client_type = TRACKING_CLIENT_TYPES.get(tracking_index).unwrap();
+
+            let started = Bridge::builder()
+                .source(tracking_client_type.clone(), None)
+                .phone_ip(ip)
+                .config(path)
+                .face_search_timeout(face_search_timeout.unsigned_abs())
+                .build()
+                .and_then(|bridge| bridge.start());
+            match started {
+                Ok(handle) => *bridge = Some(handle),
+                Err(error) => {
+                    nwg::modal_error_message(&self.window, "Sandoitchi Bridge", &error.to_string());
+                    return;
+                }
             }
-            let _ = thread::spawn(move || function(ip, sender, flag_ph));
 
             self.transform_file_path.set_readonly(true);
             self.phone_ip.set_readonly(true);
             self.file_button.set_enabled(false);
             self.connect_button.set_text("Disconnect");
         } else {
-            self.active.store(false, Ordering::Relaxed);
+            // Threads are joined on the next connect, so the window doesn't wait for them now
+            if let Some(handle) = bridge.as_ref() {
+                handle.stop();
+            }
 
             self.transform_file_path.set_readonly(false);
             self.phone_ip.set_readonly(false);